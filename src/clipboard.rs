@@ -0,0 +1,110 @@
+use crate::Terminal;
+use std::env;
+use std::io::{Error, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+//Provider
+//where copied text is sent, on top of the editor's own register:
+//Command = a clipboard tool of the desktop the editor is running in (wl-copy on wayland, xclip on X11), which can also be read back
+//Osc52 = the OSC 52 escape sequence, which asks the terminal itself to set the clipboard... this reaches the local clipboard even over
+//        SSH, but the terminal cannot be asked for the clipboard's contents, so pasting uses the register. The sequence is sent by the 
+//        terminal backend, along with everything else it draws
+//Internal = the register only, for terminals that do not understand OSC 52 (such as the linux console)
+enum Provider {
+    Command { name : &'static str, copy : &'static [&'static str], paste : &'static [&'static str] },
+    Osc52,
+    Internal,
+}
+
+pub struct Clipboard {
+    provider    : Provider,
+    register    : String,
+}
+
+impl Clipboard {
+
+    //Default
+    //picks the provider based on the environment: a clipboard tool if there is a desktop session and the tool is installed, otherwise
+    //OSC 52 unless the terminal is known not to support it
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Self {
+        let provider = if env::var_os("WAYLAND_DISPLAY").is_some() && is_installed("wl-copy") && is_installed("wl-paste") {
+            Provider::Command { name: "wl-copy", copy: &["wl-copy"], paste: &["wl-paste", "--no-newline"] }
+        } else if env::var_os("DISPLAY").is_some() && is_installed("xclip") {
+            Provider::Command { name: "xclip", copy: &["xclip", "-selection", "clipboard"], paste: &["xclip", "-selection", "clipboard", "-o"] }
+        } else if matches!(env::var("TERM").as_deref(), Ok("linux") | Ok("dumb")) {
+            Provider::Internal
+        } else {
+            Provider::Osc52
+        };
+
+        return Self {
+            provider,
+            register    : String::new(),
+        };
+    }
+
+    //a clipboard that never leaves the editor, for a terminal that is not running on anyone's desktop (such as a headless one)
+    pub fn internal() -> Self {
+        return Self {
+            provider    : Provider::Internal,
+            register    : String::new(),
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return match &self.provider {
+            Provider::Command { name, .. } => name,
+            Provider::Osc52 => "OSC 52",
+            Provider::Internal => "internal register",
+        };
+    }
+
+    //Copy
+    //the text is always kept in the register, so it can still be pasted inside the editor if the provider fails
+    pub fn copy(&mut self, text : &str, terminal : &impl Terminal) -> Result<(), Error> {
+        self.register = text.to_string();
+
+        match &self.provider {
+            Provider::Command { copy, .. } => {
+                let mut child = Command::new(copy[0]).args(&copy[1..])
+                    .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(text.as_bytes())?;
+                }
+                let status = child.wait()?;
+                if status.success() == false {
+                    return Err(Error::other(format!("{} exited with {}", copy[0], status)));
+                }
+            }
+            Provider::Osc52 => terminal.set_clipboard(text)?,
+            Provider::Internal => (),
+        }
+
+        return Ok(());
+    }
+
+    //Paste
+    //reads the clipboard back through the clipboard tool, falling back to the register if there is no tool or it fails
+    pub fn paste(&self) -> String {
+        if let Provider::Command { paste, .. } = &self.provider {
+            let output = Command::new(paste[0]).args(&paste[1..]).stdin(Stdio::null()).stderr(Stdio::null()).output();
+            if let Ok(output) = output {
+                if output.status.success() == true {
+                    return String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n");
+                }
+            }
+        }
+
+        return self.register.clone();
+    }
+}
+
+//looks for the program in every directory of $PATH
+fn is_installed(program : &str) -> bool {
+    return env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|directory| Path::new(&directory).join(program).is_file()))
+        .unwrap_or(false);
+}
@@ -0,0 +1,173 @@
+use crate::keymap::{Action, ACTION_NAMES};
+use std::fs;
+use std::path::Path;
+
+//ExCommand
+//a command typed on the command line (":w notes.txt", ":goto 120", ":set tab_width=8"...), with its argument already checked. Line
+//numbers start from 1, the way they are shown in the status bar
+pub enum ExCommand {
+    Write(Option<String>),
+    SaveAs(String),
+    Edit(String),
+    Quit(bool),
+    WriteQuit(bool),
+    Goto(usize, Option<usize>),
+    Set(Option<String>),
+    Run(Action),
+}
+
+//what a command expects after its name
+#[derive(Clone, Copy, PartialEq)]
+enum Argument {
+    Nothing,
+    File,
+    OptionalFile,
+    LineNumber,
+    Setting,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Write,
+    SaveAs,
+    Edit,
+    Quit,
+    WriteQuit,
+    Goto,
+    Set,
+}
+
+//Commands
+//the command line's own commands, by every name they can be typed as (vim's short names as well as the long ones). Each action can
+//also be run by its name (":undo", ":next_buffer"...), so anything that can be bound to a key can be done from the command line too
+const COMMANDS : &[(&str, Kind, Argument)] = &[
+    ("w",       Kind::Write,        Argument::OptionalFile),
+    ("write",   Kind::Write,        Argument::OptionalFile),
+    ("saveas",  Kind::SaveAs,       Argument::File),
+    ("e",       Kind::Edit,         Argument::File),
+    ("edit",    Kind::Edit,         Argument::File),
+    ("q",       Kind::Quit,         Argument::Nothing),
+    ("quit",    Kind::Quit,         Argument::Nothing),
+    ("wq",      Kind::WriteQuit,    Argument::Nothing),
+    ("x",       Kind::WriteQuit,    Argument::Nothing),
+    ("goto",    Kind::Goto,         Argument::LineNumber),
+    ("set",     Kind::Set,          Argument::Setting),
+];
+
+//Parse
+//a command is its name, an optional "!" (only ":q!" and ":wq!" take it, to quit even with unsaved changes) and whatever argument it
+//takes. A line number on its own (":120" or ":120:5") goes to that line, like it does in vim
+pub fn parse(line : &str) -> Result<ExCommand, String> {
+    let line = line.trim();
+    if let Some((line_number, column)) = parse_location(line) {
+        return Ok(ExCommand::Goto(line_number, column));
+    }
+
+    let name_len = line.find(|c : char| c.is_alphanumeric() == false && c != '_').unwrap_or(line.len());
+    let (name, rest) = line.split_at(name_len);
+    let (force, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let argument = Some(rest.trim()).filter(|argument| argument.is_empty() == false).map(String::from);
+
+    let (kind, expected) = match COMMANDS.iter().find(|(command, _, _)| *command == name) {
+        Some((_, kind, expected)) => (*kind, *expected),
+        None => {
+            let action = Action::from_name(name).ok_or_else(|| format!("unknown command \"{}\"", line))?;
+            if force == true || argument.is_some() {
+                return Err(format!("{} takes no argument", name));
+            }
+            return Ok(ExCommand::Run(action));
+        }
+    };
+
+    if force == true && kind != Kind::Quit && kind != Kind::WriteQuit {
+        return Err(format!("{} cannot be forced with !", name));
+    }
+    match (expected, &argument) {
+        (Argument::Nothing, Some(_))    => return Err(format!("{} takes no argument", name)),
+        (Argument::File, None)          => return Err(format!("{} needs a file name", name)),
+        (Argument::LineNumber, None)    => return Err(format!("{} needs a line number", name)),
+        _ => (),
+    }
+
+    return Ok(match kind {
+        Kind::Write     => ExCommand::Write(argument),
+        Kind::SaveAs    => ExCommand::SaveAs(argument.unwrap_or_default()),
+        Kind::Edit      => ExCommand::Edit(argument.unwrap_or_default()),
+        Kind::Quit      => ExCommand::Quit(force),
+        Kind::WriteQuit => ExCommand::WriteQuit(force),
+        Kind::Set       => ExCommand::Set(argument),
+        Kind::Goto      => {
+            let argument = argument.unwrap_or_default();
+            let (line_number, column) = parse_location(&argument).ok_or_else(|| format!("not a line number: {}", argument))?;
+            ExCommand::Goto(line_number, column)
+        }
+    });
+}
+
+//a line in a file, and maybe a column in it (both starting from 1)
+pub type Location = (usize, Option<usize>);
+
+//Parse Location
+//a place in a file, written the way compilers and grep give it: "line" or "line:column"
+pub fn parse_location(text : &str) -> Option<Location> {
+    let (line, column) = match text.split_once(':') {
+        Some((line, column)) => (line, Some(column.trim().parse::<usize>().ok()?)),
+        None => (text, None),
+    };
+    return Some((line.trim().parse::<usize>().ok()?, column));
+}
+
+//Complete
+//the ways what has been typed so far can be completed: the names of the commands that start with it, or once a command that takes a
+//file has been typed, the files and directories that start with its argument
+pub fn complete(line : &str) -> Vec<String> {
+    match line.split_once(' ') {
+        None => {
+            let mut names : Vec<&str> = COMMANDS.iter().map(|(name, _, _)| *name)
+                .chain(ACTION_NAMES.iter().map(|(_, name)| *name))
+                .filter(|name| name.starts_with(line))
+                .collect();
+            names.sort();
+            names.dedup();
+            return names.into_iter().map(String::from).collect();
+        }
+        Some((name, argument)) => {
+            let takes_file = COMMANDS.iter().any(|(command, _, expected)| {
+                *command == name && (*expected == Argument::File || *expected == Argument::OptionalFile)
+            });
+            if takes_file == false {
+                return Vec::new();
+            }
+            return complete_path(argument.trim_start()).into_iter().map(|path| format!("{} {}", name, path)).collect();
+        }
+    }
+}
+
+//the files in the directory part of the path whose names start with the rest of it. Directories end with a "/" so completing can go
+//on inside them, and hidden files are only offered when the name being completed starts with a "."
+fn complete_path(partial : &str) -> Vec<String> {
+    let (directory, prefix) = match partial.rfind('/') {
+        Some(index) => (&partial[..=index], &partial[index + 1..]),
+        None => ("", partial),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() { Path::new(".") } else { Path::new(directory) }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut paths : Vec<String> = entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let name = entry.file_name().into_string().ok()?;
+        if name.starts_with(prefix) == false || (name.starts_with('.') && prefix.starts_with('.') == false) {
+            return None;
+        }
+        let is_directory = fs::metadata(entry.path()).map(|metadata| metadata.is_dir()).unwrap_or(false);
+        return Some(format!("{}{}{}", directory, name, if is_directory == true { "/" } else { "" }));
+    }).collect();
+
+    paths.sort();
+    return paths;
+}
@@ -0,0 +1,284 @@
+use crate::gutter::LineNumbers;
+use crate::keymap::{self, Action, Keymap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use termion::color;
+use toml::Value;
+
+//the [editor] options that are turned on and off
+const FLAGS : &[&str] = &["vim_mode", "alternate_screen", "mouse"];
+
+//Config
+//the settings that can be changed through the config file. Anything the file does not mention keeps its default value, and every 
+//entry that cannot be used is reported (with the reason) instead of stopping the editor from starting
+pub struct Config {
+    pub keymap              : Keymap,
+    pub colors              : Colors,
+    pub quit_times          : u8,
+    pub message_timeout     : Duration,
+    pub tab_width           : usize,
+    pub vim_mode            : bool,
+    pub line_numbers        : LineNumbers,
+    pub alternate_screen    : bool,
+    pub mouse               : bool,
+}
+
+pub struct Colors {
+    pub status_fg           : color::Rgb,
+    pub status_bg           : color::Rgb,
+    pub inactive_status_fg  : color::Rgb,
+    pub inactive_status_bg  : color::Rgb,
+    pub selection_bg        : color::Rgb,
+    pub line_number_fg      : color::Rgb,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Self {
+            keymap              : Keymap::default(),
+            colors              : Colors {
+                status_fg           : color::Rgb(63, 63, 63),
+                status_bg           : color::Rgb(239, 239, 239),
+                inactive_status_fg  : color::Rgb(200, 200, 200),
+                inactive_status_bg  : color::Rgb(90, 90, 90),
+                selection_bg        : color::Rgb(70, 90, 140),
+                line_number_fg      : color::Rgb(130, 130, 130),
+            },
+            quit_times          : 3,
+            message_timeout     : Duration::from_secs(5),
+            tab_width           : 4,
+            vim_mode            : false,
+            line_numbers        : LineNumbers::Off,
+            alternate_screen    : true,
+            mouse               : true,
+        };
+    }
+}
+
+impl Config {
+
+    //Load
+    //reads the config file (if there is one) on top of the defaults. Returns the config along with a message for every problem found
+    //in the file
+    pub fn load() -> (Self, Vec<String>) {
+        let mut config = Config::default();
+        let mut errors : Vec<String> = Vec::new();
+
+        if let Some(path) = path() {
+            match fs::read_to_string(&path) {
+                Ok(text) => config.apply(&text, &mut errors),
+                Err(error) => errors.push(format!("could not read {}: {}", path.display(), error)),
+            }
+            for error in &mut errors {
+                *error = format!("{}: {}", path.display(), error);
+            }
+        }
+
+        return (config, errors);
+    }
+
+    //Apply
+    //the config file is made of three tables:
+    //[editor] quit_times, message_timeout (in seconds), tab_width, vim_mode, line_numbers ("off", "absolute" or "relative"),
+    //         alternate_screen (false to draw over the shell's screen and leave the last one behind, the way the editor used to) and 
+    //         mouse (false to leave the mouse to the terminal, for selecting text the terminal's own way)
+    //[colors] status_fg, status_bg, inactive_status_fg, inactive_status_bg, selection_bg and line_number_fg, as "#rrggbb" or [r, g, b]
+    //[keys]   key = "action" pairs such as "ctrl-s" = "save"... binding a key to "none" takes its default binding away
+    fn apply(&mut self, text : &str, errors : &mut Vec<String>) {
+        let table = match text.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(error) => {
+                let line = error.span().map(|span| text[..span.start].matches('\n').count() + 1).unwrap_or(1);
+                errors.push(format!("line {}: {}", line, error.message()));
+                return;
+            }
+        };
+
+        for (section, value) in &table {
+            let entries = match value.as_table() {
+                Some(entries) => entries,
+                None => {
+                    errors.push(format!("[{}] should be a table", section));
+                    continue;
+                }
+            };
+
+            for (name, value) in entries {
+                let result = match section.as_str() {
+                    "editor" => self.apply_editor_option(name, value),
+                    "colors" => self.apply_color(name, value),
+                    "keys"   => self.apply_key_binding(name, value),
+                    _        => Err("unknown section".to_string()),
+                };
+
+                if let Err(error) = result {
+                    errors.push(format!("{}.{}: {}", section, name, error));
+                }
+            }
+        }
+    }
+
+    fn apply_editor_option(&mut self, name : &str, value : &Value) -> Result<(), String> {
+        if FLAGS.contains(&name) {
+            let flag = value.as_bool().ok_or_else(|| "expected true or false".to_string())?;
+            match name {
+                "vim_mode"          => self.vim_mode = flag,
+                "alternate_screen"  => self.alternate_screen = flag,
+                _                   => self.mouse = flag,
+            }
+            return Ok(());
+        }
+        if name == "line_numbers" {
+            //true and false work as well, for absolute line numbers and none
+            self.line_numbers = match value.as_bool() {
+                Some(true) => LineNumbers::Absolute,
+                Some(false) => LineNumbers::Off,
+                None => value.as_str().and_then(LineNumbers::from_name).ok_or_else(|| "expected \"off\", \"absolute\" or \"relative\"".to_string())?,
+            };
+            return Ok(());
+        }
+
+        let number = value.as_integer().ok_or_else(|| "expected a number".to_string())?;
+
+        match name {
+            "quit_times" => {
+                self.quit_times = u8::try_from(number).map_err(|_| "expected a number from 0 to 255".to_string())?;
+            }
+            "message_timeout" => {
+                let seconds = u64::try_from(number).map_err(|_| "expected a number of seconds".to_string())?;
+                self.message_timeout = Duration::from_secs(seconds);
+            }
+            "tab_width" => {
+                self.tab_width = usize::try_from(number).ok().filter(|width| (1..=16).contains(width))
+                    .ok_or_else(|| "expected a number from 1 to 16".to_string())?;
+            }
+            _ => return Err("unknown option".to_string()),
+        }
+
+        return Ok(());
+    }
+
+    //Set
+    //changes an [editor] option while the editor is running, from a setting written the way vim's ":set" takes it: "tab_width=8",
+    //"vim_mode" / "novim_mode" to turn an option on / off and "tab_width?" to see its value. A few vim names ("tabstop", "number"...)
+    //work too. Returns the option as it is afterwards, to show to the user
+    pub fn set(&mut self, setting : &str) -> Result<String, String> {
+        let (name, value) = match setting.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (setting.trim(), None),
+        };
+        let is_flag = |name : &str| FLAGS.contains(&option_name(name)) || option_name(name) == "line_numbers";
+        let (name, value) = match value {
+            Some(value) => (name, Some(value)),
+            None if name.ends_with('?') => (&name[..name.len() - 1], None),
+            None if is_flag(name) == true => (name, Some("true")),
+            None if name.strip_prefix("no").map(is_flag) == Some(true) => (&name[2..], Some("false")),
+            None => (name, None),
+        };
+        let name = option_name(name);
+
+        if let Some(value) = value {
+            //the value is read as TOML, so it means the same thing it would in the config file, but strings do not need quotes
+            let table = format!("value = {}", value).parse::<toml::Table>().ok();
+            let parsed = table.and_then(|mut table| table.remove("value")).unwrap_or_else(|| Value::String(value.to_string()));
+            self.apply_editor_option(name, &parsed).map_err(|error| format!("{}: {}", name, error))?;
+        }
+
+        let value = self.option(name).ok_or_else(|| format!("unknown option \"{}\"", name))?;
+        return Ok(format!("{}={}", name, value));
+    }
+
+    //every [editor] option with its current value, the way ":set" with nothing after it shows them
+    pub fn options(&self) -> String {
+        let names = ["quit_times", "message_timeout", "tab_width", "vim_mode", "line_numbers", "alternate_screen", "mouse"];
+        return names.iter().map(|name| format!("{}={}", name, self.option(name).unwrap_or_default())).collect::<Vec<_>>().join(" ");
+    }
+
+    fn option(&self, name : &str) -> Option<String> {
+        return match name {
+            "quit_times"        => Some(self.quit_times.to_string()),
+            "message_timeout"   => Some(self.message_timeout.as_secs().to_string()),
+            "tab_width"         => Some(self.tab_width.to_string()),
+            "vim_mode"          => Some(self.vim_mode.to_string()),
+            "line_numbers"      => Some(self.line_numbers.name().to_string()),
+            "alternate_screen"  => Some(self.alternate_screen.to_string()),
+            "mouse"             => Some(self.mouse.to_string()),
+            _                   => None,
+        };
+    }
+
+    fn apply_color(&mut self, name : &str, value : &Value) -> Result<(), String> {
+        let color = parse_color(value).ok_or_else(|| "expected a colour such as \"#3f3f3f\" or [63, 63, 63]".to_string())?;
+
+        match name {
+            "status_fg"             => self.colors.status_fg = color,
+            "status_bg"             => self.colors.status_bg = color,
+            "inactive_status_fg"    => self.colors.inactive_status_fg = color,
+            "inactive_status_bg"    => self.colors.inactive_status_bg = color,
+            "selection_bg"          => self.colors.selection_bg = color,
+            "line_number_fg"        => self.colors.line_number_fg = color,
+            _                       => return Err("unknown colour".to_string()),
+        }
+
+        return Ok(());
+    }
+
+    fn apply_key_binding(&mut self, name : &str, value : &Value) -> Result<(), String> {
+        let key = keymap::parse_key(name).ok_or_else(|| "unknown key".to_string())?;
+        let action_name = value.as_str().ok_or_else(|| "expected the name of an action".to_string())?;
+
+        if action_name == "none" {
+            self.keymap.bind(key, None);
+            return Ok(());
+        }
+
+        let action = Action::from_name(action_name).ok_or_else(|| format!("unknown action \"{}\"", action_name))?;
+        self.keymap.bind(key, Some(action));
+        return Ok(());
+    }
+}
+
+//the [editor] option a name given to ":set" stands for, as vim's names for them work as well
+fn option_name(name : &str) -> &str {
+    return match name {
+        "tabstop" | "ts"    => "tab_width",
+        "vim"               => "vim_mode",
+        "number" | "nu"     => "line_numbers",
+        name                => name,
+    };
+}
+
+//"#rrggbb" or an array of three numbers from 0 to 255
+fn parse_color(value : &Value) -> Option<color::Rgb> {
+    if let Some(text) = value.as_str() {
+        let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii())?;
+        let channel = |index : usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+        return Some(color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+
+    let channels : Vec<u8> = value.as_array()?.iter().map(|channel| channel.as_integer().and_then(|channel| u8::try_from(channel).ok())).collect::<Option<_>>()?;
+    if let [red, green, blue] = channels[..] {
+        return Some(color::Rgb(red, green, blue));
+    }
+    return None;
+}
+
+//Path
+//the first config file found, looking in $XDG_CONFIG_HOME (falling back to ~/.config) and then in each of $XDG_CONFIG_DIRS (falling 
+//back to /etc/xdg)... the file is text_editor/config.toml inside one of those directories
+pub fn path() -> Option<PathBuf> {
+    let mut directories : Vec<PathBuf> = Vec::new();
+
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if dir.is_empty() == false => directories.push(PathBuf::from(dir)),
+        _ => directories.extend(env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))),
+    }
+    match env::var_os("XDG_CONFIG_DIRS") {
+        Some(dirs) if dirs.is_empty() == false => directories.extend(env::split_paths(&dirs)),
+        _ => directories.push(PathBuf::from("/etc/xdg")),
+    }
+
+    return directories.into_iter().map(|directory| directory.join("text_editor").join("config.toml")).find(|path| path.is_file());
+}
@@ -1,43 +1,682 @@
 use crate::Position;
 use crate::Row;
+use crate::SearchDirection;
+use crate::filetype::FileType;
+use crate::highlighting;
+use crate::row::display;
+use crate::swap::{self, SwapId};
+use regex::Regex;
 use std::fs;
-use std::io::{Error, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use ropey::{Rope, RopeBuilder, RopeSlice};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, UnicodeSegmentation};
 
 
+#[derive(Clone, Copy, PartialEq)]
+enum EditKind {
+    Insert,
+    Delete,
+}
+
+//Edit
+//A single entry on the undo/redo stack. It records what was changed (the text that was inserted or deleted, and where), along with the
+//cursor position before and after the change so that undo/redo can put the cursor back where the user expects it. new_row is set when
+//an insert created a brand new row at the bottom of the document, as undoing it has to remove the row (or rows) rather than just its contents.
+//Edits that share a group number are undone and redone together
+struct Edit {
+    kind            : EditKind,
+    group           : usize,
+    at              : Position,
+    text            : String,
+    new_row         : bool,
+    cursor_before   : Position,
+    cursor_after    : Position,
+}
+
+//History
+//undo holds the edits that have been applied, redo the ones that have been undone. saved_at is the length of the undo stack at the time
+//of the last save (None if that state can no longer be reached), and sealed stops the next typed character from being merged into the
+//previous edit (set after a save, undo or redo). While open_group is set, every recorded edit joins that group... group_depth allows
+//groups to be nested, only the outermost group counts
+struct History {
+    undo        : Vec<Edit>,
+    redo        : Vec<Edit>,
+    saved_at    : Option<usize>,
+    sealed      : bool,
+    next_group  : usize,
+    open_group  : Option<usize>,
+    group_depth : usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        return Self {
+            undo        : Vec::new(),
+            redo        : Vec::new(),
+            saved_at    : Some(0),
+            sealed      : false,
+            next_group  : 0,
+            open_group  : None,
+            group_depth : 0,
+        };
+    }
+}
+
+impl History {
+
+    fn record(&mut self, mut edit : Edit) {
+        self.redo.clear();
+        if let Some(saved_at) = self.saved_at {
+            if saved_at > self.undo.len() {
+                self.saved_at = None;
+            }
+        }
+
+        //consecutive typed characters are grouped into a single undo step
+        if let Some(last) = self.undo.last_mut() {
+            if self.sealed == false
+                && edit.kind == EditKind::Insert
+                && last.kind == EditKind::Insert
+                && edit.text != "\n"
+                && last.text.ends_with('\n') == false
+                && edit.cursor_before == last.cursor_after {
+                last.text.push_str(&edit.text);
+                last.cursor_after = edit.cursor_after;
+                return;
+            }
+        }
+
+        edit.group = match self.open_group {
+            Some(group) => group,
+            None => self.new_group(),
+        };
+        self.sealed = false;
+        self.undo.push(edit);
+    }
+
+    fn new_group(&mut self) -> usize {
+        self.next_group = self.next_group + 1;
+        return self.next_group;
+    }
+
+    fn is_at_saved_state(&self) -> bool {
+        return self.saved_at == Some(self.undo.len());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            LineEnding::Lf   => "\n",
+            LineEnding::CrLf => "\r\n",
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            LineEnding::Lf   => "LF",
+            LineEnding::CrLf => "CRLF",
+        };
+    }
+}
+
+//File Format
+//the parts of the file on disk that are not kept in the rope: which line ending it uses, whether the last line ends with a line 
+//ending, and whether it starts with a UTF-8 byte order mark. They are remembered when the file is opened and written back on save so
+//that saving an untouched file gives back exactly the same bytes
+#[derive(Clone, Copy)]
+pub struct FileFormat {
+    pub line_ending     : LineEnding,
+    pub final_newline   : bool,
+    pub bom             : bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        return Self {
+            line_ending     : LineEnding::Lf,
+            final_newline   : true,
+            bom             : false,
+        };
+    }
+}
+
+const BOM : &[u8] = b"\xEF\xBB\xBF";
+
+//Graphemes
+//walks through the grapheme boundaries of a row in the rope. The rope hands out its text in chunks, and the grapheme cursor is given
+//the next chunk (or the one before, for context) whenever it asks for it
+struct Graphemes<'a> {
+    line        : RopeSlice<'a>,
+    cursor      : GraphemeCursor,
+    chunk       : &'a str,
+    chunk_start : usize,
+}
+
+impl<'a> Graphemes<'a> {
+    fn create(line : RopeSlice<'a>) -> Self {
+        return Self::create_at(line, 0);
+    }
+
+    //starts the walk at the given byte of the row, which has to be a grapheme boundary
+    fn create_at(line : RopeSlice<'a>, byte : usize) -> Self {
+        let (chunk, chunk_start, _, _) = line.chunk_at_byte(byte);
+        return Self {
+            line,
+            cursor      : GraphemeCursor::new(byte, line.len_bytes(), true),
+            chunk,
+            chunk_start,
+        };
+    }
+
+    //Advance
+    //moves past `count` graphemes, returning the byte index it ends up at (None if the row is not that long)
+    fn advance(&mut self, count : usize) -> Option<usize> {
+        let mut left = count;
+        while left > 0 {
+            let ascii = self.skip_ascii(left);
+            if ascii > 0 {
+                left = left - ascii;
+            } else {
+                self.next_boundary()?;
+                left = left - 1;
+            }
+        }
+        return Some(self.cursor.cur_cursor());
+    }
+
+    //how many graphemes are left in the row, from where the walker is
+    fn len(mut self) -> usize {
+        let mut len : usize = 0;
+        loop {
+            let ascii = self.skip_ascii(usize::MAX);
+            if ascii > 0 {
+                len = len + ascii;
+            } else if self.next_boundary().is_some() {
+                len = len + 1;
+            } else {
+                return len;
+            }
+        }
+    }
+
+    //Skip ASCII
+    //moves past up to `most` graphemes that are a single ASCII byte each, returning how many it moved past. Two ASCII bytes in a row are
+    //always separate graphemes (apart from "\r\n"), so runs of them are skipped over without asking the grapheme cursor
+    fn skip_ascii(&mut self, most : usize) -> usize {
+        let offset = self.cursor.cur_cursor() - self.chunk_start;
+        let bytes = self.chunk.as_bytes().get(offset..).unwrap_or_default();
+        let ascii = bytes.windows(2)
+            .take(most)
+            .take_while(|pair| pair[0].is_ascii() && pair[1].is_ascii() && pair != b"\r\n")
+            .count();
+
+        self.cursor.set_cursor(self.chunk_start + offset + ascii);
+        return ascii;
+    }
+
+    //the byte index of the next boundary (None at the end of the row)
+    fn next_boundary(&mut self) -> Option<usize> {
+        loop {
+            match self.cursor.next_boundary(self.chunk, self.chunk_start) {
+                Ok(boundary) => return boundary,
+                Err(GraphemeIncomplete::NextChunk) => {
+                    self.chunk_start = self.chunk_start + self.chunk.len();
+                    self.chunk = self.line.chunk_at_byte(self.chunk_start).0;
+                }
+                Err(GraphemeIncomplete::PreContext(index)) => {
+                    let (context, context_start, _, _) = self.line.chunk_at_byte(index - 1);
+                    self.cursor.provide_context(context, context_start);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+//the graphemes of the row from where the walker is... they are borrowed from the rope, unless one is split between two chunks
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        let start = self.cursor.cur_cursor();
+        let end = if self.skip_ascii(1) == 1 { start + 1 } else { self.next_boundary()? };
+
+        if start >= self.chunk_start {
+            return Some(Cow::Borrowed(&self.chunk[start - self.chunk_start..end - self.chunk_start]));
+        }
+        return Some(Cow::Owned(self.line.byte_slice(start..end).to_string()));
+    }
+}
+
+//Checkpoint
+//a place along a long row that a walk through its graphemes can start from, rather than from the start of the row: the index of the
+//grapheme, the byte it starts at and the screen column it is drawn at. One is left every CHECKPOINT_EVERY graphemes
+#[derive(Clone, Copy)]
+struct Checkpoint {
+    grapheme    : usize,
+    byte        : usize,
+    column      : usize,
+}
+
+const CHECKPOINT_EVERY : usize = 1024;
+
+//the checkpoints of each row that has them, along with the tab width their columns were worked out for
+type Checkpoints = BTreeMap<usize, (usize, Vec<Checkpoint>)>;
+
+//Walk
+//the graphemes of a row along with the index and screen column of each, from a checkpoint on. at and column are where the walk has got
+//to, and checkpoints are left along the way for the walks that come after it
+struct Walk<'a> {
+    graphemes   : Graphemes<'a>,
+    checkpoints : &'a RefCell<Checkpoints>,
+    index       : usize,
+    tab_width   : usize,
+    at          : usize,
+    column      : usize,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (usize, usize, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<(usize, usize, Cow<'a, str>)> {
+        let byte = self.graphemes.cursor.cur_cursor();
+        let grapheme = self.graphemes.next()?;
+
+        if self.at > 0 && self.at.is_multiple_of(CHECKPOINT_EVERY) {
+            let mut checkpoints = self.checkpoints.borrow_mut();
+            let (_, row) = checkpoints.entry(self.index).or_insert((self.tab_width, Vec::new()));
+            if row.last().is_none_or(|last| last.grapheme < self.at) {
+                row.push(Checkpoint { grapheme: self.at, byte, column: self.column });
+            }
+        }
+
+        let item = (self.at, self.column, grapheme);
+        self.column = self.column + display(&item.2, self.column, self.tab_width).1;
+        self.at = self.at + 1;
+        return Some(item);
+    }
+}
+
+//Read Text
+//the file is read one line at a time straight into the rope, rather than loading the whole thing into a string first. Line endings are
+//stored as plain '\n's in the rope (with a '\n' added if the file does not end with one). If the first line ends in "\r\n" (and 
+//`crlf` allows it) the file is read as CRLF, and its '\r's are left out of the rope... a later line that ends in a plain '\n' means the
+//file mixes line endings, and None is returned. Any other '\r' stays in the rope
+fn read_text(filename : &str, crlf : bool) -> Result<Option<(Rope, FileFormat)>, Error> {
+    let mut reader = BufReader::new(fs::File::open(filename)?);
+    let mut builder = RopeBuilder::new();
+    let mut line : Vec<u8> = Vec::new();
+    let mut format = FileFormat::default();
+    let mut first_line = true;
+
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        if first_line == true && line.starts_with(BOM) {
+            format.bom = true;
+            line.drain(..BOM.len());
+        }
+
+        format.final_newline = line.ends_with(b"\n");
+        if line.ends_with(b"\n") {
+            line.pop();
+            if first_line == true && crlf == true && line.ends_with(b"\r") {
+                format.line_ending = LineEnding::CrLf;
+            }
+            if format.line_ending == LineEnding::CrLf {
+                if line.ends_with(b"\r") == false {
+                    return Ok(None);
+                }
+                line.pop();
+            }
+        }
+        first_line = false;
+
+        let value = std::str::from_utf8(&line).map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        builder.append(value);
+        builder.append("\n");
+        line.clear();
+    }
+
+    return Ok(Some((builder.finish(), format)));
+}
+
+fn with_context(error : Error, context : &str) -> Error {
+    return Error::new(error.kind(), format!("{}: {}", context, error));
+}
+
+//Document
+//The text is kept in a rope rather than a vector of strings, so that inserting or deleting in the middle of a large file (or of a very
+//long line) only touches a small part of it. Every row in the rope is terminated by a '\n', which means the number of rows is one less
+//than the number of lines ropey counts (an empty rope has no rows at all, "\n" has a single empty row)
 #[derive(Default)]
 pub struct Document {
-    rows            : Vec<Row>,
+    text            : Rope,
     pub file_name   : Option<String>,
     dirty           : bool,
+    history         : History,
+    format          : FileFormat,
+    swap_id         : SwapId,
+    swap_file       : Option<PathBuf>,
+    swap_pending    : bool,
+    recovery        : Option<PathBuf>,
+    ignored_swap    : Option<PathBuf>,
+    file_type       : FileType,
+    highlighted     : Vec<(Vec<highlighting::Type>, highlighting::State)>,
+    checkpoints     : RefCell<Checkpoints>,
 }
 
 impl Document {
 
+    //Open
+    //the line ending style is taken from the first line, and along with the final newline and byte order mark it is kept in the 
+    //document's format for when it is saved. A file that mixes line endings is read again as LF, so that the lines ending in "\r\n" keep
+    //their '\r' and every line is written back the way it was
     pub fn open(filename : &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
-        let mut rows : Vec<Row> = Vec::new();
-        
-        for value in contents.lines() {
-            rows.push(Row::create(value));
-        }
+        let (text, format) = match read_text(filename, true)? {
+            Some(read) => read,
+            None => read_text(filename, false)?.unwrap_or_default(),
+        };
         
+        //a swap file that is newer than the file means the editor died with unsaved changes, which the user can choose to recover
+        let recovery = swap::recovery_file(filename);
+
         return Ok( Self { 
-                    rows,
+                    text,
                     file_name   : Some(filename.to_string()),
                     dirty       : false,
+                    history     : History::default(),
+                    format,
+                    swap_id     : SwapId::default(),
+                    swap_file   : None,
+                    swap_pending: false,
+                    recovery,
+                    ignored_swap: None,
+                    file_type   : FileType::from(filename),
+                    highlighted : Vec::new(),
+                    checkpoints : RefCell::default(),
                 });
     }
 
-    pub fn row(&self, index : usize) -> Option<&Row> {
-        return self.rows.get(index);
+    //No Name
+    //an empty document that is not a file yet. If an editor that is no longer running left the swap file of a [No Name] buffer behind,
+    //it is offered for recovery here, as there is no file it would be found next to
+    pub fn no_name() -> Self {
+        return Self {
+            recovery    : swap::orphaned_no_name(),
+            ..Self::default()
+        };
+    }
+
+    //Row
+    //builds a Row for the given line of the rope (without its '\n'), which copies the whole line... rows are only created for wrapping,
+    //highlighting and regex searches, and the rest of the editor measures and draws rows with the functions below instead
+    pub fn row(&self, index : usize) -> Option<Row> {
+        if index >= self.len() {
+            return None;
+        }
+
+        return Some(Row::create(&Cow::from(self.line(index))));
+    }
+
+    //Row Length
+    //how many graphemes the row has (0 past the end of the document). Like the measures after it, it reads the row straight from the
+    //rope, so moving the cursor around a very long row does not copy the whole row every time
+    pub fn row_len(&self, index : usize) -> usize {
+        if index >= self.len() {
+            return 0;
+        }
+        return Graphemes::create(self.line(index)).len();
+    }
+
+    //the graphemes of the row, from the start
+    pub fn graphemes(&self, index : usize) -> impl Iterator<Item = Cow<'_, str>> {
+        let line = if index < self.len() { self.line(index) } else { self.text.slice(0..0) };
+        return Graphemes::create(line);
+    }
+
+    //Walk
+    //walks through the row from the last checkpoint that `before` holds for (the checkpoints come in order, and `before` holds for the
+    //ones up to some point and not after it), or from the start of the row if there is none. The checkpoints of a row are worked out 
+    //for one tab width, and are started over if the row is walked with another
+    fn walk(&self, index : usize, tab_width : usize, before : impl Fn(&Checkpoint) -> bool) -> Walk<'_> {
+        let line = if index < self.len() { self.line(index) } else { self.text.slice(0..0) };
+        let mut checkpoints = self.checkpoints.borrow_mut();
+        let start = match checkpoints.get_mut(&index) {
+            Some((width, row)) if *width == tab_width => row[..row.partition_point(|checkpoint| before(checkpoint))].last().copied(),
+            Some(row) => {
+                *row = (tab_width, Vec::new());
+                None
+            }
+            None => None,
+        };
+        let start = start.unwrap_or(Checkpoint { grapheme: 0, byte: 0, column: 0 });
+
+        return Walk {
+            graphemes   : Graphemes::create_at(line, start.byte),
+            checkpoints : &self.checkpoints,
+            index,
+            tab_width,
+            at          : start.grapheme,
+            column      : start.column,
+        };
+    }
+
+    //Seek
+    //the graphemes of the row from grapheme `at` on, along with the byte it starts at (None if the row is not that long). The walk there
+    //starts from the last checkpoint before `at`, whichever tab width the checkpoints were left for, as only their columns depend on it
+    fn seek(&self, index : usize, at : usize) -> (Graphemes<'_>, Option<usize>) {
+        let checkpoints = self.checkpoints.borrow();
+        let start = checkpoints.get(&index)
+            .and_then(|(_, row)| row[..row.partition_point(|checkpoint| checkpoint.grapheme <= at)].last().copied())
+            .unwrap_or(Checkpoint { grapheme: 0, byte: 0, column: 0 });
+
+        let line = if index < self.len() { self.line(index) } else { self.text.slice(0..0) };
+        let mut graphemes = Graphemes::create_at(line, start.byte);
+        let byte = graphemes.advance(at - start.grapheme);
+        return (graphemes, byte);
+    }
+
+    //Column
+    //the screen column grapheme `at` of the row starts at, the same way Row::column works it out. Past the end of the row (or of the 
+    //document) every grapheme counts as one column
+    pub fn column(&self, index : usize, at : usize, tab_width : usize) -> usize {
+        let mut walk = self.walk(index, tab_width, |checkpoint| checkpoint.grapheme <= at);
+        while walk.at < at && walk.next().is_some() {}
+        return walk.column + at - walk.at;
+    }
+
+    //how many screen columns the whole row takes up
+    pub fn width(&self, index : usize, tab_width : usize) -> usize {
+        let mut walk = self.walk(index, tab_width, |_| true);
+        while walk.next().is_some() {}
+        return walk.column;
+    }
+
+    //the grapheme of the row that covers the given screen column, or the end of the row if the column is past it
+    pub fn index_at(&self, index : usize, column : usize, tab_width : usize) -> usize {
+        let mut walk = self.walk(index, tab_width, |checkpoint| checkpoint.column <= column);
+        while let Some((at, _, _)) = walk.next() {
+            if walk.column > column {
+                return at;
+            }
+        }
+        return walk.at;
+    }
+
+    //whether the row has a grapheme at `at`... only the graphemes up to it are walked through, rather than counting the whole row
+    pub fn has_grapheme(&self, index : usize, at : usize) -> bool {
+        return index < self.len() && Graphemes::create(self.line(index)).advance(at + 1).is_some();
+    }
+
+    //Render
+    //returns what is shown of the row between the screen columns start and end, as runs of text that share the same highlighting type
+    //so that each run can be drawn in its own colour. The text covers every column from start up to end (or up to the end of the row),
+    //and a wide grapheme cut in half by either edge is drawn as spaces, so nothing after it is pushed out of place. Runs are also split
+    //where the selected graphemes (from the first index of `selected` up to the second) start and end, and say whether they are selected.
+    //The graphemes are read straight from the rope, from the last checkpoint before `start` up to `end`, so drawing any part of a very 
+    //long row costs no more than drawing a short one once the row has been walked through that far
+    pub fn render(&self, index : usize, start : usize, end : usize, tab_width : usize, selected : Option<(usize, usize)>) -> Vec<(highlighting::Type, bool, String)> {
+        let mut result : Vec<(highlighting::Type, bool, String)> = Vec::new();
+        let highlighting = self.highlighted.get(index).map(|(highlighting, _)| &highlighting[..]).unwrap_or_default();
+
+        for (at, column, grapheme) in self.walk(index, tab_width, |checkpoint| checkpoint.column <= start) {
+            if column >= end {
+                break;
+            }
+            let (text, width) = display(&grapheme, column, tab_width);
+            let next = column + width;
+
+            if next > start {
+                let highlighting_type = highlighting.get(at).copied().unwrap_or(highlighting::Type::None);
+                let is_selected = selected.is_some_and(|(from, to)| from <= at && at < to);
+                let text = if column < start || next > end {
+                    Cow::Owned(" ".repeat(cmp::min(next, end) - cmp::max(column, start)))
+                } else {
+                    text
+                };
+
+                match result.last_mut() {
+                    Some((last_type, last_selected, last_text)) if *last_type == highlighting_type && *last_selected == is_selected => {
+                        last_text.push_str(&text);
+                    }
+                    _ => result.push((highlighting_type, is_selected, text.into_owned())),
+                }
+            }
+        }
+
+        return result;
+    }
+
+    pub fn file_type(&self) -> &FileType {
+        return &self.file_type;
+    }
+
+    //Highlight
+    //makes sure the highlighting of every row before `until` is up to date. The highlighting of each row is kept along with the state it
+    //ends in (for multi-line comments and strings), so only the rows after the last edit need to be worked out again... editing a row 
+    //throws away the highlighting from that row onwards, as the edit can change how every row after it is highlighted. A file type with 
+    //nothing to highlight (such as plain text) is skipped altogether, so its rows are never built for it
+    pub fn highlight(&mut self, until : usize) {
+        if self.file_type.highlighting_options().is_empty() {
+            return;
+        }
+        let until = cmp::min(until, self.len());
+
+        while self.highlighted.len() < until {
+            let index = self.highlighted.len();
+            let state = self.highlighted.last().map(|(_, state)| *state).unwrap_or_default();
+            let mut row = self.row(index).unwrap_or_default();
+            let state = row.highlight(self.file_type.highlighting_options(), state);
+            self.highlighted.push((row.take_highlighting(), state));
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        return self.rows.is_empty();
+        return self.text.len_chars() == 0;
     }
 
     pub fn len(&self) -> usize {
-        return self.rows.len();
+        return self.text.len_lines() - 1;
+    }
+
+    //Find
+    //looks for the query starting at the given position and moving in the given direction, one row at a time. When the end (or the
+    //start) of the document is reached the search wraps around, so every row is checked once, plus the starting row a second time for 
+    //matches on the other side of the starting position
+    pub fn find(&self, query : &str, at : &Position, direction : SearchDirection) -> Option<Position> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+
+        let mut position = *at;
+        if position.y >= len {
+            position = match direction {
+                SearchDirection::Forward  => Position { x: 0, y: 0 },
+                SearchDirection::Backward => Position { x: self.row_len(len - 1), y: len - 1 },
+            };
+        }
+
+        for _ in 0..=len {
+            if let Some(x) = self.find_in_row(position.y, query, position.x, direction) {
+                return Some(Position { x, y: position.y });
+            }
+
+            if direction == SearchDirection::Forward {
+                position.y = (position.y + 1) % len;
+                position.x = 0;
+            } else {
+                position.y = (position.y + len - 1) % len;
+                position.x = self.row_len(position.y);
+            }
+        }
+
+        return None;
+    }
+
+    //Find In Row
+    //searches the row straight from the rope, forwards starting at grapheme `at` or backwards ending just before it, so that a search
+    //does not copy every row it goes through. Matches are only looked for where a grapheme starts, so they line up with the cursor
+    fn find_in_row(&self, index : usize, query : &str, at : usize, direction : SearchDirection) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+
+        let line = self.line(index);
+        let (start, limit) = match direction {
+            SearchDirection::Forward  => (at, line.len_bytes()),
+            SearchDirection::Backward => (0, self.seek(index, at).1.unwrap_or(line.len_bytes())),
+        };
+
+        let (mut graphemes, byte) = self.seek(index, start);
+        let mut byte = byte?;
+        let mut x = start;
+        let mut found = None;
+        while byte + query.len() <= limit {
+            if line.bytes_at(byte).take(query.len()).eq(query.bytes()) {
+                found = Some(x);
+                if direction == SearchDirection::Forward {
+                    break;
+                }
+            }
+
+            byte = match graphemes.next_boundary() {
+                Some(boundary) => boundary,
+                None => break,
+            };
+            x = x + 1;
+        }
+
+        return found;
+    }
+
+    //Find Regex
+    //finds every match of the regular expression in the given row. Returns where each match starts, how many graphemes it covers, and
+    //the replacement text with any capture group references ($1, ${name}...) expanded
+    pub fn find_regex(&self, regex : &Regex, index : usize, replacement : &str) -> Vec<(usize, usize, String)> {
+        let row = match self.row(index) {
+            Some(row) => row,
+            None => return Vec::new(),
+        };
+
+        return row.find_regex(regex).into_iter().map(|(x, len, captures)| {
+            let mut expanded = String::new();
+            captures.expand(replacement, &mut expanded);
+            (x, len, expanded)
+        }).collect();
     }
 
     //-------------------------------------------------//
@@ -48,67 +687,574 @@ impl Document {
             return;
         }
         self.dirty = true;
+        self.swap_pending = true;
 
+        let new_row = at.y == self.len();
+        let cursor_after = self.insert_char(at, c);
+        self.history.record(Edit {
+            kind            : EditKind::Insert,
+            group           : 0,
+            at              : *at,
+            text            : c.to_string(),
+            new_row,
+            cursor_before   : *at,
+            cursor_after,
+        });
+    }
+
+    pub fn delete(&mut self, at : &Position) {
+        if at.y >= self.len() {
+            return;
+        }
+
+        let text = match self.delete_grapheme(at) {
+            Some(text) => text,
+            None => return,
+        };
+
+        self.dirty = true;
+        self.swap_pending = true;
+        self.history.record(Edit {
+            kind            : EditKind::Delete,
+            group           : 0,
+            at              : *at,
+            text,
+            new_row         : false,
+            cursor_before   : *at,
+            cursor_after    : *at,
+        });
+    }
+
+    //Insert Str
+    //inserts text that may span several rows (such as pasted text) as a single undo step, and returns the position just after it
+    pub fn insert_str(&mut self, at : &Position, text : &str) -> Position {
+        if at.y > self.len() || text.is_empty() {
+            return *at;
+        }
+        self.dirty = true;
+        self.swap_pending = true;
+
+        let new_row = at.y == self.len();
+        let cursor_after = self.insert_text(at, text);
+        self.history.sealed = true;
+        self.history.record(Edit {
+            kind            : EditKind::Insert,
+            group           : 0,
+            at              : *at,
+            text            : text.to_string(),
+            new_row,
+            cursor_before   : *at,
+            cursor_after,
+        });
+        self.history.sealed = true;
+
+        return cursor_after;
+    }
+
+    //Text Range
+    //returns the text between two positions (which can be on different rows), with rows joined by '\n'. The '\n' at the end of the
+    //last row is never part of the range
+    pub fn text_range(&self, start : &Position, end : &Position) -> String {
+        let last = self.text.len_chars().saturating_sub(1);
+        let start = cmp::min(self.char_index(start), last);
+        let end = cmp::min(self.char_index(end), last);
+        if start >= end {
+            return String::new();
+        }
+
+        return self.text.slice(start..end).to_string();
+    }
+
+    //Delete Range
+    //deletes the text between two positions as a single undo step, and returns the text that was deleted
+    pub fn delete_range(&mut self, start : &Position, end : &Position) -> String {
+        let text = self.text_range(start, end);
+        if text.is_empty() {
+            return text;
+        }
+        self.dirty = true;
+        self.swap_pending = true;
+
+        self.delete_text(start, &text);
+        self.history.sealed = true;
+        self.history.record(Edit {
+            kind            : EditKind::Delete,
+            group           : 0,
+            at              : *start,
+            text            : text.clone(),
+            new_row         : false,
+            cursor_before   : *start,
+            cursor_after    : *start,
+        });
+        self.history.sealed = true;
+
+        return text;
+    }
+
+    //Replace
+    //replaces `len` graphemes at the given position with the text, as a single undo step. Returns the position just after the 
+    //inserted text
+    pub fn replace(&mut self, at : &Position, len : usize, text : &str) -> Position {
+        self.start_undo_group();
+        for _ in 0..len {
+            self.delete(at);
+        }
+
+        let position = self.insert_str(at, text);
+        self.end_undo_group();
+
+        return position;
+    }
+
+    //Undo Groups
+    //every edit made between start_undo_group and end_undo_group is undone (and redone) as a single step... this is used by commands 
+    //such as replace that are made of many smaller edits
+    pub fn start_undo_group(&mut self) {
+        if self.history.group_depth == 0 {
+            let group = self.history.new_group();
+            self.history.open_group = Some(group);
+            self.history.sealed = true;
+        }
+        self.history.group_depth = self.history.group_depth + 1;
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.history.group_depth = self.history.group_depth.saturating_sub(1);
+        if self.history.group_depth == 0 {
+            self.history.open_group = None;
+            self.history.sealed = true;
+        }
+    }
+
+    //Undo
+    //reverts the most recent edit (or group of edits) and moves it onto the redo stack. Returns the position the cursor should be moved 
+    //to, or None if there was nothing to undo. If this brings the document back to the last saved state, the document is no longer 
+    //considered dirty
+    pub fn undo(&mut self) -> Option<Position> {
+        let mut cursor = None;
+
+        while let Some(edit) = self.history.undo.pop() {
+            match edit.kind {
+                EditKind::Insert => {
+                    if edit.new_row == true {
+                        self.changed(&edit.at, false);
+                        let start = self.text.line_to_char(edit.at.y);
+                        let end = self.text.len_chars();
+                        self.text.remove(start..end);
+                    } else {
+                        self.delete_text(&edit.at, &edit.text);
+                    }
+                }
+                EditKind::Delete => {
+                    self.insert_text(&edit.at, &edit.text);
+                }
+            }
+
+            cursor = Some(edit.cursor_before);
+            let group = edit.group;
+            self.history.redo.push(edit);
+            if self.history.undo.last().map(|last| last.group) != Some(group) {
+                break;
+            }
+        }
+
+        self.history.sealed = true;
+        self.dirty = self.history.is_at_saved_state() == false;
+        self.swap_pending = true;
+        return cursor;
+    }
+
+    //Redo
+    //re-applies the most recently undone edit (or group of edits), returning the position the cursor should be moved to
+    pub fn redo(&mut self) -> Option<Position> {
+        let mut cursor = None;
+
+        while let Some(edit) = self.history.redo.pop() {
+            match edit.kind {
+                EditKind::Insert => {
+                    self.insert_text(&edit.at, &edit.text);
+                }
+                EditKind::Delete => {
+                    self.delete_text(&edit.at, &edit.text);
+                }
+            }
+
+            cursor = Some(edit.cursor_after);
+            let group = edit.group;
+            self.history.undo.push(edit);
+            if self.history.redo.last().map(|last| last.group) != Some(group) {
+                break;
+            }
+        }
+
+        self.history.sealed = true;
+        self.dirty = self.history.is_at_saved_state() == false;
+        self.swap_pending = true;
+        return cursor;
+    }
+
+    //the functions below change the rows without touching the undo history... they are shared by the public editing functions
+    //and by undo/redo. insert_char returns the position directly after the inserted character
+    fn insert_char(&mut self, at : &Position, c : char) -> Position {
+        self.changed(at, c != '\n');
         if c == '\n' {
             self.insert_newline(at);
-            return;
+            return Position { x: 0, y: at.y + 1 };
+        }
+
+        if at.y == self.len() {
+            let end = self.text.len_chars();
+            self.text.insert(end, &format!("{}\n", c));
+            return Position { x: 1, y: at.y };
         }
 
+        let index = self.char_index(at);
+        self.text.insert_char(index, c);
+        return Position { x: at.x + 1, y: at.y };
+    }
+
+    //inserts the text in one go (it may contain '\n's), returning the position just after it. Text added after the last row needs a '\n'
+    //to end its row, unless it already ends with one (like insert_char and insert_newline do, so that redo gives back the same rows)
+    fn insert_text(&mut self, at : &Position, text : &str) -> Position {
+        self.changed(at, text.contains('\n') == false);
         if at.y == self.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            self.rows.push(row);
+            let end = self.text.len_chars();
+            self.text.insert(end, text);
+            if text.ends_with('\n') == false {
+                self.text.insert_char(self.text.len_chars(), '\n');
+            }
         } else {
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.insert(at.x, c);
+            let index = self.char_index(at);
+            self.text.insert(index, text);
         }
+
+        let rows = text.split('\n').count();
+        let last_row_len = text.rsplit('\n').next().unwrap_or_default().graphemes(true).count();
+        if rows == 1 {
+            return Position { x: at.x + last_row_len, y: at.y };
+        }
+        return Position { x: last_row_len, y: at.y + rows - 1 };
     }
 
-    pub fn delete(&mut self, at : &Position) {
-        let len = self.len();
-        
-        if at.y >= len {
-            return;
+    fn delete_text(&mut self, at : &Position, text : &str) {
+        self.changed(at, text.contains('\n') == false);
+        let start = self.char_index(at);
+        let end = cmp::min(start + text.chars().count(), self.text.len_chars());
+        self.text.remove(start..end);
+    }
+
+    //removes the grapheme at the position and returns it, or None if there is nothing there to remove
+    fn delete_grapheme(&mut self, at : &Position) -> Option<String> {
+        let (start, mut end) = self.grapheme_range(at)?;
+        let joined = start == end;
+        if joined == true {
+            //at the end of the row, removing its '\n' joins it with the next row (the last row has none to join with)
+            if at.y + 1 >= self.len() {
+                return None;
+            }
+            end = start + 1;
         }
 
-        self.dirty = true;
+        self.changed(at, joined == false);
+        let text = self.text.slice(start..end).to_string();
+        self.text.remove(start..end);
+        return Some(text);
+    }
 
-        if at.x == self.rows.get_mut(at.y).unwrap().get_len() && at.y < len - 1 {
-            let next_row = self.rows.remove(at.y + 1);
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.append(&next_row);
-        } else {
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.delete(at.x);
+    //Changed
+    //forgets what was worked out about the rows from an edit at the given position on: the highlighting of its row and every row after
+    //it, and the checkpoints of its row past the position. The checkpoints of the rows after it are only kept if the edit stayed within 
+    //the row, as otherwise the rows have moved
+    fn changed(&mut self, at : &Position, within_row : bool) {
+        self.highlighted.truncate(at.y);
+        let checkpoints = self.checkpoints.get_mut();
+        let after = checkpoints.split_off(&(at.y + 1));
+        if let Some((_, row)) = checkpoints.get_mut(&at.y) {
+            row.truncate(row.partition_point(|checkpoint| checkpoint.grapheme < at.x));
+        }
+        if within_row == true {
+            checkpoints.extend(after);
         }
     }
 
     fn insert_newline(&mut self, at : &Position) {
 
         if at.y == self.len() {
-            self.rows.push(Row::default());
+            let end = self.text.len_chars();
+            self.text.insert_char(end, '\n');
             return;
         }
 
-        let new_row = self.rows.get_mut(at.y).unwrap().split(at.x);
-        self.rows.insert(at.y + 1, new_row);
+        let index = self.char_index(at);
+        self.text.insert_char(index, '\n');
+    }
+
+    //converts a position (in graphemes) into an index (in chars) in the rope... a position past the end of its row is taken as the end
+    fn char_index(&self, at : &Position) -> usize {
+        return match self.grapheme_range(at) {
+            Some((start, _)) => start,
+            None => self.text.line_to_char(at.y) + self.line(at.y).len_chars(),
+        };
+    }
+
+    //Grapheme Range
+    //the chars in the rope covered by the grapheme at the position (an empty range at the end of the row, and None past it). Only the 
+    //graphemes before the position are walked through, rather than building the whole Row, so an edit near the start of a very long row
+    //does not cost as much as the row is long
+    fn grapheme_range(&self, at : &Position) -> Option<(usize, usize)> {
+        if at.y > self.len() {
+            return None;
+        }
+
+        let line = self.line(at.y);
+        let mut graphemes = Graphemes::create(line);
+        let start = graphemes.advance(at.x)?;
+        let end = graphemes.next_boundary().unwrap_or(start);
+
+        let line_start = self.text.line_to_char(at.y);
+        return Some((line_start + line.byte_to_char(start), line_start + line.byte_to_char(end)));
+    }
+
+    //the text of a row in the rope, without its '\n'
+    fn line(&self, index : usize) -> RopeSlice<'_> {
+        let line = self.text.line(index);
+        return line.slice(..line.len_chars().saturating_sub(1));
     }
 
+    //Save
+    //the document is first written to a temporary file next to the target, which is flushed all the way to the disk and given the same
+    //permissions as the original file, and is then renamed over the target. The rename either happens completely or not at all, so if 
+    //anything goes wrong part way through (a crash, a full disk...) the original file is left untouched. Errors say which step failed
     pub fn save(&mut self) -> Result<(), Error> {
         if let Some(file_name) = &self.file_name {
-            let mut file = fs::File::create(file_name)?;
-            for row in &self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-            }
+            self.write_to(file_name)?;
+
             self.dirty = false;
+            self.history.saved_at = Some(self.history.undo.len());
+            //saving under a new name can change the language of the file
+            let file_type = FileType::from(file_name);
+
+            self.history.sealed = true;
+            self.remove_swap_file();
+
+            if file_type.name() != self.file_type.name() {
+                self.file_type = file_type;
+                self.highlighted.clear();
+            }
+        }
+
+        return Ok(());
+    }
+
+    //Write To
+    //writes the document to the given file the same way save does, without making it the document's file... the document stays dirty
+    //if it was, as its own file has not been written
+    pub fn write_to(&self, file_name : &str) -> Result<(), Error> {
+        //if the file is a symlink, replace the file it points to rather than the link itself
+        let target = fs::canonicalize(file_name).unwrap_or_else(|_| PathBuf::from(file_name));
+        let permissions = fs::metadata(&target).map(|metadata| metadata.permissions()).ok();
+        return self.write_replacing(&target, permissions);
+    }
+
+    //Write Replacing
+    //writes the document to a temporary file next to the target and renames it over the target once it is all on disk, so that the
+    //target is never left half written. The temporary file gets the given permissions from the start (a new file gets the usual ones),
+    //so the text in it is never readable by anyone the target itself does not let read it
+    fn write_replacing(&self, target : &Path, permissions : Option<fs::Permissions>) -> Result<(), Error> {
+        let directory = match target.parent() {
+            Some(parent) if parent.as_os_str().is_empty() == false => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let name = target.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let temporary = directory.join(format!(".{}.{}.tmp", name, process::id()));
+
+        let mode = permissions.as_ref().map(|permissions| permissions.mode() & 0o777).unwrap_or(0o666);
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temporary)
+            .map_err(|error| with_context(error, "could not create temporary file"))?;
+
+        //only the temporary file created above is removed if something goes wrong... one that was already there is not this write's
+        if let Err(error) = self.write_atomically(file, permissions, target, &temporary, &directory) {
+            let _ = fs::remove_file(&temporary);
+            return Err(error);
+        }
+
+        return Ok(());
+    }
+
+    fn write_atomically(&self, file : fs::File, permissions : Option<fs::Permissions>, target : &Path, temporary : &Path, directory : &Path) -> Result<(), Error> {
+        let mut writer = BufWriter::new(file);
+        self.write_contents(&mut writer).map_err(|error| with_context(error, "could not write file"))?;
+        let file = writer.into_inner().map_err(|error| with_context(error.into_error(), "could not write file"))?;
+        file.sync_all().map_err(|error| with_context(error, "could not flush file to disk"))?;
+
+        //the umask can leave the new file with fewer permissions than the target has, so they are copied over exactly
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions).map_err(|error| with_context(error, "could not copy file permissions"))?;
+        }
+
+        fs::rename(temporary, target).map_err(|error| with_context(error, "could not replace file"))?;
+
+        //the rename is only kept through a crash once the directory holding it has reached the disk as well (some platforms cannot
+        //sync a directory, so that failure is ignored)
+        if let Ok(directory) = fs::File::open(directory) {
+            let _ = directory.sync_all();
         }
 
         return Ok(());
     }
 
+    fn write_contents(&self, writer : &mut impl Write) -> Result<(), Error> {
+        let ending = self.format.line_ending.as_str();
+
+        if self.format.bom == true {
+            writer.write_all(BOM)?;
+        }
+        for index in 0..self.len() {
+            let line = self.text.line(index);
+            for chunk in line.slice(..line.len_chars() - 1).chunks() {
+                writer.write_all(chunk.as_bytes())?;
+            }
+            if index + 1 < self.len() || self.format.final_newline == true {
+                writer.write_all(ending.as_bytes())?;
+            }
+        }
+
+        return writer.flush();
+    }
+
     pub fn is_dirty(&self) -> bool {
         return self.dirty;
     }
 
+    pub fn format(&self) -> &FileFormat {
+        return &self.format;
+    }
+
+    //-------------------------------------------------//
+    //------------ Swap File And Recovery -------------//
+    //-------------------------------------------------//
+
+    //Write Swap File
+    //writes the unsaved changes to the swap file, if anything has changed since it was last written. A document that is back to its 
+    //saved state does not need a swap file at all, so it is removed instead. If the file has been given a new name since the last swap
+    //file was written, the old swap file is removed as well
+    pub fn write_swap_file(&mut self) -> Result<(), Error> {
+        if self.swap_pending == false {
+            return Ok(());
+        }
+
+        if self.dirty == false {
+            self.remove_swap_file();
+            self.swap_pending = false;
+            return Ok(());
+        }
+
+        let mut path = swap::path_for(self.file_name.as_deref(), &self.swap_id).ok_or_else(|| Error::new(ErrorKind::NotFound, "no place to put the swap file"))?;
+        if self.ignored_swap.as_ref() == Some(&path) {
+            path = swap::alternate(&path);
+        }
+        if self.swap_file.as_ref() != Some(&path) {
+            self.remove_swap_file();
+        }
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        //the swap file holds the same text as the file, so it gets the same permissions (and only the user can read a [No Name] one).
+        //It is replaced as a whole like the file is when saving, so dying part way through a write still leaves the last swap file
+        let mode = self.file_name.as_ref().and_then(|file_name| fs::metadata(file_name).ok()).map(|metadata| metadata.permissions().mode() & 0o777).unwrap_or(0o600);
+        self.swap_file = Some(path.clone());
+        //a write that failed is tried again the next time, rather than leaving a swap file behind that is missing the changes
+        self.write_replacing(&path, Some(fs::Permissions::from_mode(mode)))?;
+        self.swap_pending = false;
+        return Ok(());
+    }
+
+    pub fn remove_swap_file(&mut self) {
+        if let Some(path) = self.swap_file.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    pub fn recovery_file(&self) -> Option<&Path> {
+        return self.recovery.as_deref();
+    }
+
+    //Recover
+    //replaces the contents of the document with the contents of the swap file. The document is dirty afterwards, as what is on the 
+    //screen no longer matches the file on disk. The swap file is taken over by this document and will be rewritten or removed by it
+    pub fn recover(&mut self) -> Result<(), Error> {
+        if let Some(path) = self.recovery.take() {
+            let swap = Document::open(&path.to_string_lossy())?;
+            self.text = swap.text;
+            self.highlighted.clear();
+            self.checkpoints.get_mut().clear();
+            self.history = History { saved_at: None, ..History::default() };
+            self.dirty = true;
+            self.swap_pending = true;
+            self.swap_file = Some(path);
+        }
+
+        return Ok(());
+    }
+
+    //Swap Differences
+    //compares the document with the swap file that was found when it was opened, and returns the first row where they differ along
+    //with the contents of the swap file, as a [No Name] document that can be shown next to this one. Returns None if both are the same
+    pub fn swap_differences(&self) -> Result<Option<(usize, Document)>, Error> {
+        let path = match &self.recovery {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let swap = Document::open(&path.to_string_lossy())?;
+
+        let mut start = 0;
+        while start < self.len() && start < swap.len() && self.text.line(start) == swap.text.line(start) {
+            start = start + 1;
+        }
+
+        if start == self.len() && start == swap.len() {
+            return Ok(None);
+        }
+
+        //highlighted the same way as the file, but saving it asks for a name rather than writing over the swap file
+        let file_type = match &self.file_name {
+            Some(file_name) => FileType::from(file_name),
+            None => FileType::default(),
+        };
+        return Ok(Some((start, Document { text: swap.text, format: swap.format, file_type, ..Document::default() })));
+    }
+
+    pub fn delete_recovery_file(&mut self) -> Result<(), Error> {
+        if let Some(path) = self.recovery.take() {
+            fs::remove_file(path)?;
+        }
+
+        return Ok(());
+    }
+
+    //the swap file is left as it is, to be offered again the next time the file is opened... this document's own swap file goes by
+    //the other name meanwhile
+    pub fn ignore_recovery_file(&mut self) {
+        self.ignored_swap = self.recovery.take();
+    }
+
+    //Set Line Ending
+    //changes the line ending used when the document is saved. This changes every line of the file on disk, so the document becomes
+    //dirty and undoing can no longer bring it back to its saved state
+    pub fn set_line_ending(&mut self, line_ending : LineEnding) {
+        if self.format.line_ending == line_ending {
+            return;
+        }
+
+        self.format.line_ending = line_ending;
+        self.dirty = true;
+        self.swap_pending = true;
+        self.history.saved_at = None;
+    }
+
 }
\ No newline at end of file
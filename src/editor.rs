@@ -1,23 +1,50 @@
 use crate::Document;
-use crate::Row;
+use crate::LineEnding;
+use crate::row;
 use crate::Terminal;
-use termion::event::Key;
+use crate::clipboard::Clipboard;
+use crate::command::{self, ExCommand, Location};
+use crate::config::Config;
+use crate::gutter::Gutter;
+use crate::keymap::{self, Action};
+use crate::modal::{self, Command, InsertAt, Modal, Mode, Motion, Operator};
+use crate::swap::SWAP_INTERVAL;
+use crate::terminal::{Event, TermionTerminal};
+use crate::layout::{Layout, Rect, SplitDirection};
+use termion::event::{Key, MouseButton, MouseEvent};
+use regex::Regex;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
 use std::env;
-use termion::color;
-use std::time::Duration;
+use std::io::ErrorKind;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::path::Path;
+use std::rc::Rc;
 use std::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-const STATUS_FG_COLOR : color::Rgb = color::Rgb(63, 63, 63);
-const STATUS_BG_COLOR : color::Rgb = color::Rgb(239, 239, 239);
+const RESIZE_STEP : i16 = 5;
+const WHEEL_LINES : usize = 3;
+const MAX_PASTE : usize = 64 * 1024 * 1024;
 const VERSION : &str = env!("CARGO_PKG_VERSION");
-const QUIT_TIMES : u8 = 3;
+const SWAP_FILES_KEPT : &str = "Unsaved changes were kept in swap files, and can be recovered when the files are opened again.";
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
 pub struct Position {
     pub x : usize,
     pub y : usize,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+#[allow(non_camel_case_types)]
 struct Status_Message {
     text    : String,
     time    : Instant,
@@ -32,70 +59,950 @@ impl Status_Message {
     }
 }
 
-pub struct Editor {
+//Buffer
+//an open document, along with where the cursor and the view were the last time it was shown... the editor's cursor_position and
+//offset always belong to the current buffer, and are stored here while another buffer is being shown. Wrapping long rows is turned
+//on and off for each buffer, in every pane that shows it
+struct Buffer {
+    document        : Document,
+    cursor_position : Position,
+    offset          : Position,
+    wrap            : bool,
+}
+
+impl Buffer {
+    fn create(document : Document) -> Self {
+        return Self {
+            document,
+            cursor_position : Position::default(),
+            offset          : Position::default(),
+            wrap            : false,
+        };
+    }
+}
+
+//Screen Line
+//the part of a row shown on one line of a pane: the row's columns from start up to end, and whether this is the first line of the
+//row (the one its line number goes next to)
+struct ScreenLine {
+    row     : usize,
+    start   : usize,
+    end     : usize,
+    first   : bool,
+}
+
+//Pane
+//a part of the screen showing one of the buffers, with its own cursor and view. Several panes can show the same buffer, and as they all
+//share its document, an edit made in one of them shows up in the others straight away. Just like with buffers, the editor's 
+//cursor_position and offset belong to the pane that has the focus, and are stored here while another pane has it
+struct Pane {
+    buffer          : usize,
+    cursor_position : Position,
+    offset          : Position,
+}
+
+//Wrapped Row
+//where each line of a wrapped row starts (its first grapheme and the screen column of it), and how wide the whole row is
+struct WrappedRow {
+    lines   : Vec<(usize, usize)>,
+    width   : usize,
+}
+
+//the rows wrapped while drawing a frame, by buffer, row and width
+type WrappedRows = HashMap<(usize, usize, usize), Rc<WrappedRow>>;
+
+//Editor
+//the editor, drawing on and reading its input from a terminal... the one it is running in, unless it is given another backend (such
+//as a headless one in tests)
+pub struct Editor<T : Terminal = TermionTerminal> {
     should_quit     : bool,
-    terminal        : Terminal,
+    terminal        : T,
     cursor_position : Position,
     offset          : Position,
     status_message  : Status_Message,  
-    document        : Document,
+    buffers         : Vec<Buffer>,
+    current_buffer  : usize,
+    panes           : Vec<Pane>,
+    current_pane    : usize,
+    layout          : Layout,
     quit_times      : u8,
+    last_swap       : Instant,
+    selection_anchor: Option<Position>,
+    mouse_anchor    : Option<Position>,
+    clipboard       : Clipboard,
+    config          : Config,
+    modal           : Modal,
+    wrapped         : RefCell<Option<WrappedRows>>,
 }
 
-impl Editor {
+impl Editor<TermionTerminal> {
 
     //------------------------------------------------------------------------//
     //------------------ Default Editor Implementation -----------------------//
     //------------------------------------------------------------------------//
+    //the editor in the terminal it was started from, opening the files named on the command line with the user's config
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Self {
-
         let args : Vec<String> = env::args().collect();
-        let mut initial_status = String::from("HELP: Ctrl-W = write | HELP: Alt-Q = quit");
+        let (config, config_errors) = Config::load();
+        let terminal = TermionTerminal::default().expect("Failed to initilize terminal");
+        return Editor::create(terminal, &args[1..], config, &config_errors);
+    }
+}
 
-        let document = if args.len() > 1 {
-            let file_name = &args[1];
-            let doc = Document::open(&file_name);
-            if doc.is_ok() {
-                doc.unwrap()
-            } else {
-                initial_status = format!("ERR: could not open file: {}", file_name);
-                Document::default()
+impl<T : Terminal> Editor<T> {
+
+    //Create
+    //the editor on the given terminal, opening the files named in `args` (which are read like the command line's, so they can give
+    //a line to open each file at). Errors from loading the config are shown in the message bar, unless a file could not be opened
+    pub fn create(terminal : T, args : &[String], config : Config, config_errors : &[String]) -> Self {
+        let mut initial_status = config.keymap.help();
+        let mut buffers : Vec<Buffer> = Vec::new();
+        let mut locations : Vec<Option<Location>> = Vec::new();
+        let mut failed : Vec<String> = Vec::new();
+
+        for (file_name, location) in file_arguments(args) {
+            match Document::open(&file_name) {
+                Ok(doc) => {
+                    buffers.push(Buffer::create(doc));
+                    locations.push(location);
+                }
+                Err(_) => failed.push(file_name),
             }
-        } else {
-            Document::default()
-        };
+        }
+
+        if failed.is_empty() == false {
+            initial_status = format!("ERR: could not open file: {}", failed.join(", "));
+        } else if let Some(error) = config_errors.first() {
+            initial_status = config_error_message(error, config_errors.len());
+        }
+        if buffers.is_empty() == true {
+            buffers.push(Buffer::create(Document::no_name()));
+        }
 
-        return Editor{ 
+        let clipboard = terminal.clipboard();
+        let mut editor = Editor{ 
             should_quit     : false,
-            terminal        : Terminal::default().expect("Failed to initilize terminal"),
+            terminal,
             cursor_position : Position::default(),
             offset          : Position::default(),
             status_message  : Status_Message::create(initial_status),
-            document,
-            quit_times      : QUIT_TIMES,
+            buffers,
+            current_buffer  : 0,
+            panes           : vec![Pane { buffer: 0, cursor_position: Position::default(), offset: Position::default() }],
+            current_pane    : 0,
+            layout          : Layout::Pane(0),
+            quit_times      : config.quit_times,
+            last_swap       : Instant::now(),
+            selection_anchor: None,
+            mouse_anchor    : None,
+            clipboard,
+            config,
+            modal           : Modal::default(),
+            wrapped         : RefCell::new(None),
          };
+
+        editor.terminal.set_alternate_screen(editor.config.alternate_screen);
+        editor.terminal.set_mouse(editor.config.mouse);
+
+        //files opened at a given line are scrolled to it now that the size of the screen is known
+        for (index, location) in locations.into_iter().enumerate().rev() {
+            if let Some((line, column)) = location {
+                editor.switch_buffer(index);
+                editor.go_to(line, column);
+            }
+        }
+        editor.switch_buffer(0);
+        return editor;
     }
 
     //------------------------------------------------------------------------//
     //---------------- Run Text Editor (Wrapper Function_) -------------------//
     //------------------------------------------------------------------------//
+    //runs until the user quits. If the input ends first (the terminal has gone away, the editor was sent SIGHUP or SIGTERM, or a headless
+    //one has run out of events) there is no one left to ask about unsaved changes, so they are kept in swap files like when the editor 
+    //dies, but it stops without panicking
+    //A panic is caught on its way out so that unsaved changes can be kept as well (the panic hook has put the terminal back by then),
+    //and then carries on
     pub fn run(&mut self) {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.event_loop())) {
+            Ok(Ok(())) => {
+                for buffer in &mut self.buffers {
+                    buffer.document.remove_swap_file();
+                }
+            }
+            Ok(Err(error)) if error.kind() == ErrorKind::UnexpectedEof => self.write_swap_files(),
+            Ok(Err(error)) => self.die(error),
+            Err(panic) => {
+                self.write_swap_files();
+                if self.dirty_buffers() > 0 {
+                    eprintln!("{}", SWAP_FILES_KEPT);
+                }
+                panic::resume_unwind(panic);
+            }
+        }
+    }
+
+    fn event_loop(&mut self) -> Result<(), std::io::Error> {
+        for index in (0..self.buffers.len()).rev() {
+            self.switch_buffer(index);
+            self.check_recovery_file()?;
+        }
 
         loop {
-            if let Err(error) = self.refresh_screen() {
-                self.die(error);
+            self.refresh_screen()?;
+            if self.should_quit == true {
+                return Ok(());
             }
+            self.process_keypress()?;
+            self.update_swap_file();
+        }
+    }
 
-            if self.should_quit == true {
+    //------------------------------------------------------------------------//
+    //--------------------------- Struct Getters -----------------------------//
+    //------------------------------------------------------------------------//
+    pub fn terminal(&self) -> &T {
+        return &self.terminal;
+    }
+
+    //the document of the current buffer
+    pub fn document(&self) -> &Document {
+        return &self.buffers[self.current_buffer].document;
+    }
+
+    pub fn cursor_position(&self) -> Position {
+        return self.cursor_position;
+    }
+
+    pub fn status_message(&self) -> &str {
+        return &self.status_message.text;
+    }
+
+    //------------------------------------------------------------------------//
+    //--------------------- Swap File And Recovery ---------------------------//
+    //------------------------------------------------------------------------//
+    //keeps whatever has not been saved yet, so it can be recovered the next time the file is opened
+    fn write_swap_files(&mut self) {
+        for buffer in &mut self.buffers {
+            let _ = buffer.document.write_swap_file();
+        }
+    }
+
+    //Update Swap File
+    //writes the unsaved changes of every buffer to its swap file, at most once every SWAP_INTERVAL... it runs after every event, and 
+    //the terminal sends a tick when nothing else has happened for that long
+    fn update_swap_file(&mut self) {
+        if Instant::now() - self.last_swap < SWAP_INTERVAL {
+            return;
+        }
+
+        self.last_swap = Instant::now();
+        for buffer in &mut self.buffers {
+            if let Err(error) = buffer.document.write_swap_file() {
+                self.status_message = Status_Message::create(format!("Could not write swap file: {}", error));
+            }
+        }
+    }
+
+    //Check Recovery File
+    //if a swap file newer than the file was found when the document was opened, the editor most likely died with unsaved changes. The
+    //user can recover them, see the swap file next to the file (both starting at the first difference), delete the swap file or 
+    //ignore it
+    fn check_recovery_file(&mut self) -> Result<(), std::io::Error> {
+        let swap_file = match self.document().recovery_file() {
+            Some(path) => path.display().to_string(),
+            None => return Ok(()),
+        };
+        let mut question = format!("Found swap file {}. (r)ecover / (d)iff / (x) delete / (i)gnore", swap_file);
+
+        loop {
+            self.status_message = Status_Message::create(question.clone());
+            self.refresh_screen()?;
+
+            match self.terminal.read_key()? {
+                Key::Char('r') => {
+                    let message = match self.document_mut().recover() {
+                        Ok(()) => "Recovered unsaved changes from the swap file.".to_string(),
+                        Err(error) => format!("Could not recover swap file: {}", error),
+                    };
+                    self.status_message = Status_Message::create(message);
+                    return Ok(());
+                }
+                Key::Char('d') => {
+                    let summary = self.show_recovery_file();
+                    question = format!("{} (r)ecover / (x) delete / (i)gnore", summary);
+                }
+                Key::Char('x') => {
+                    let message = match self.document_mut().delete_recovery_file() {
+                        Ok(()) => "Swap file deleted.".to_string(),
+                        Err(error) => format!("Could not delete swap file: {}", error),
+                    };
+                    self.status_message = Status_Message::create(message);
+                    return Ok(());
+                }
+                Key::Char('i') | Key::Esc => {
+                    self.document_mut().ignore_recovery_file();
+                    self.status_message = Status_Message::create(String::new());
+                    return Ok(());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    //Show Recovery File
+    //opens the contents of the swap file in a buffer of its own, in a pane next to the file, with both scrolled to the first row that
+    //differs. The focus goes back to the file, as that is what the rest of the question is about
+    fn show_recovery_file(&mut self) -> String {
+        let (first, swap) = match self.document().swap_differences() {
+            Ok(Some(differences)) => differences,
+            Ok(None) => return "The swap file is the same as the file.".to_string(),
+            Err(error) => return format!("Could not read swap file: {}", error),
+        };
+
+        let file_pane = self.current_pane;
+        self.cursor_position = Position { x: 0, y: first };
+        self.scroll();
+        self.split_pane(SplitDirection::Vertical);
+        self.buffers.push(Buffer::create(swap));
+        self.switch_buffer(self.buffers.len() - 1);
+        self.cursor_position = Position { x: 0, y: first };
+        self.scroll();
+        self.focus_pane(file_pane);
+
+        return format!("The swap file is shown on the right, from line {}.", first + 1);
+    }
+
+    //------------------------------------------------------------------------//
+    //------------------------- Buffer Management ----------------------------//
+    //------------------------------------------------------------------------//
+    fn document_mut(&mut self) -> &mut Document {
+        return &mut self.buffers[self.current_buffer].document;
+    }
+
+    //Switch Buffer
+    //the cursor and view of the current buffer are stored in it, and the ones of the new buffer are brought back, so each buffer opens
+    //where it was left
+    fn switch_buffer(&mut self, index : usize) {
+        if index >= self.buffers.len() {
+            return;
+        }
+
+        let current = &mut self.buffers[self.current_buffer];
+        current.cursor_position = self.cursor_position;
+        current.offset = self.offset;
+
+        self.current_buffer = index;
+        self.selection_anchor = None;
+        self.panes[self.current_pane].buffer = index;
+        self.cursor_position = self.buffers[index].cursor_position;
+        self.offset = self.buffers[index].offset;
+    }
+
+    fn next_buffer(&mut self) {
+        self.switch_buffer((self.current_buffer + 1) % self.buffers.len());
+    }
+
+    fn previous_buffer(&mut self) {
+        self.switch_buffer((self.current_buffer + self.buffers.len() - 1) % self.buffers.len());
+    }
+
+    //Open File
+    //asks for a file name and opens it in a new buffer (or switches to it, if it is already open)
+    fn open_file(&mut self) -> Result<(), std::io::Error> {
+        let file_name = match self.prompt("Open file: ", |_, _, _| {})? {
+            Some(file_name) if file_name.is_empty() == false => file_name,
+            _ => {
+                self.status_message = Status_Message::create("Open aborted.".to_string());
+                return Ok(());
+            }
+        };
+
+        return self.open_path(file_name);
+    }
+
+    fn open_path(&mut self, file_name : String) -> Result<(), std::io::Error> {
+        if let Some(index) = self.buffers.iter().position(|buffer| buffer.document.file_name.as_ref() == Some(&file_name)) {
+            self.switch_buffer(index);
+            return Ok(());
+        }
+
+        match Document::open(&file_name) {
+            Ok(document) => {
+                self.buffers.push(Buffer::create(document));
+                self.switch_buffer(self.buffers.len() - 1);
+                self.check_recovery_file()?;
+            }
+            Err(error) => {
+                self.status_message = Status_Message::create(format!("ERR: could not open file: {}: {}", file_name, error));
+            }
+        }
+
+        return Ok(());
+    }
+
+    //------------------------------------------------------------------------//
+    //-------------------------- Pane Management -----------------------------//
+    //------------------------------------------------------------------------//
+
+    //the whole screen apart from the message bar is shared between the panes
+    fn screen_area(&self) -> Rect {
+        let size = self.terminal.size();
+        return Rect { x: 0, y: 0, width: size.width as usize, height: size.height as usize };
+    }
+
+    fn pane_area(&self) -> Rect {
+        let areas = self.layout.areas(self.screen_area());
+        return areas.iter().find(|(pane, _)| *pane == self.current_pane).map(|(_, area)| *area).unwrap_or_else(|| self.screen_area());
+    }
+
+    //the part of the focused pane the text is drawn in (everything but its status bar and the gutter)
+    fn text_area(&self) -> Rect {
+        let area = self.pane_area();
+        let gutter = cmp::min(self.gutter(self.document()).width(), area.width);
+        return Rect { x: area.x + gutter, width: area.width - gutter, height: area.height.saturating_sub(1), ..area };
+    }
+
+    fn gutter(&self, document : &Document) -> Gutter {
+        return Gutter::create(self.config.line_numbers, document.len());
+    }
+
+    //the buffer, cursor and offset of a pane, taking them from the editor for the pane that has the focus
+    fn pane_view(&self, pane : usize) -> (usize, Position, Position) {
+        if pane == self.current_pane {
+            return (self.current_buffer, self.cursor_position, self.offset);
+        }
+
+        let pane = &self.panes[pane];
+        return (pane.buffer, pane.cursor_position, pane.offset);
+    }
+
+    //Focus Pane
+    //stores the cursor and view of the focused pane and brings back the ones of the newly focused pane. Its document may have been 
+    //changed from another pane in the meantime, so the cursor is moved back inside the document if it needs to be
+    fn focus_pane(&mut self, index : usize) {
+        if index >= self.panes.len() {
+            return;
+        }
+
+        let current = &mut self.panes[self.current_pane];
+        current.cursor_position = self.cursor_position;
+        current.offset = self.offset;
+        self.load_pane(index);
+    }
+
+    fn load_pane(&mut self, index : usize) {
+        self.current_pane = index;
+        self.selection_anchor = None;
+        self.current_buffer = self.panes[index].buffer;
+        self.cursor_position = self.panes[index].cursor_position;
+        self.offset = self.panes[index].offset;
+
+        let len = self.document().len();
+        if self.cursor_position.y > len {
+            self.cursor_position = Position { x: 0, y: len };
+        }
+        let row_len = self.document().row_len(self.cursor_position.y);
+        self.cursor_position.x = cmp::min(self.cursor_position.x, row_len);
+        self.scroll();
+    }
+
+    //the panes are cycled through in the order they appear on the screen
+    fn next_pane(&mut self, forwards : bool) {
+        let order : Vec<usize> = self.layout.areas(self.screen_area()).iter().map(|(pane, _)| *pane).collect();
+        let position = order.iter().position(|pane| *pane == self.current_pane).unwrap_or(0);
+        let next = if forwards == true {
+            (position + 1) % order.len()
+        } else {
+            (position + order.len() - 1) % order.len()
+        };
+
+        self.focus_pane(order[next]);
+    }
+
+    //Split Pane
+    //the new pane shows the same buffer at the same place as the focused pane, and gets the focus
+    fn split_pane(&mut self, direction : SplitDirection) {
+        self.panes.push(Pane { 
+            buffer          : self.current_buffer, 
+            cursor_position : self.cursor_position, 
+            offset          : self.offset,
+        });
+
+        let new_pane = self.panes.len() - 1;
+        self.layout.split(self.current_pane, new_pane, direction);
+        self.focus_pane(new_pane);
+    }
+
+    //Close Pane
+    //the space of the closed pane goes to the pane it was split from. The buffer it was showing stays open
+    fn close_pane(&mut self) {
+        if self.panes.len() == 1 {
+            self.status_message = Status_Message::create("Cannot close the last pane.".to_string());
+            return;
+        }
+
+        let closed = self.current_pane;
+        self.layout.remove(closed);
+        self.panes.remove(closed);
+        self.load_pane(cmp::min(closed, self.panes.len() - 1));
+    }
+
+    fn resize_pane(&mut self, amount : i16) {
+        if self.layout.resize(self.current_pane, amount) == false {
+            self.status_message = Status_Message::create("There is only one pane.".to_string());
+        }
+        self.scroll();
+    }
+
+    //Window Command
+    //Alt-W is followed by a second key that says what to do with the panes:
+    //s = split horizontally (one pane above the other), v = split vertically (side by side), w / W = focus the next / previous pane, 
+    //c = close the pane, + / - = make the pane bigger / smaller
+    fn window_command(&mut self) -> Result<(), std::io::Error> {
+        self.status_message = Status_Message::create("Window: (s)plit / (v)split / (w) next / (W) previous / (c)lose / (+) grow / (-) shrink".to_string());
+        self.refresh_screen()?;
+
+        let key = self.terminal.read_key()?;
+        self.status_message = Status_Message::create(String::new());
+
+        match key {
+            Key::Char('s') => self.split_pane(SplitDirection::Horizontal),
+            Key::Char('v') => self.split_pane(SplitDirection::Vertical),
+            Key::Char('w') => self.next_pane(true),
+            Key::Char('W') => self.next_pane(false),
+            Key::Char('c') => self.close_pane(),
+            Key::Char('+') => self.resize_pane(RESIZE_STEP),
+            Key::Char('-') => self.resize_pane(-RESIZE_STEP),
+            _ => (),
+        }
+
+        return Ok(());
+    }
+
+    //------------------------------------------------------------------------//
+    //---------------------------- Selection ---------------------------------//
+    //------------------------------------------------------------------------//
+
+    //Selection
+    //the selected text runs from the anchor (set with Alt-M) to the cursor, so moving the cursor grows or shrinks it. The two ends are
+    //returned in the order they appear in the document, or None if nothing is selected
+    //In vim's visual mode the grapheme under the cursor is part of the selection as well
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection_anchor?;
+        let cursor = self.cursor_position;
+        let (start, end) = if (anchor.y, anchor.x) <= (cursor.y, cursor.x) { (anchor, cursor) } else { (cursor, anchor) };
+
+        if self.in_mode(Mode::Visual) == true {
+            return Some((start, modal::next_position(self.document(), end)));
+        }
+        if start == end {
+            return None;
+        }
+        return Some((start, end));
+    }
+
+    fn copy_selection(&mut self) {
+        if let Some((start, end)) = self.selection() {
+            let text = self.document().text_range(&start, &end);
+            self.selection_anchor = None;
+            self.yank(&text, "Copied");
+        }
+    }
+
+    fn cut_selection(&mut self) {
+        if self.selection().is_some() {
+            let text = self.delete_selection();
+            self.yank(&text, "Cut");
+        }
+    }
+
+    //puts the text on the clipboard and says so in the message bar
+    fn yank(&mut self, text : &str, verb : &str) {
+        let count = text.chars().count();
+        let message = match self.clipboard.copy(text, &self.terminal) {
+            Ok(()) => format!("{} {} character(s) to the clipboard ({}).", verb, count, self.clipboard.name()),
+            Err(error) => format!("{} {} character(s), but the {} clipboard failed: {}", verb, count, self.clipboard.name(), error),
+        };
+        self.status_message = Status_Message::create(message);
+    }
+
+    fn paste(&mut self) {
+        let text = self.clipboard.paste();
+        self.insert_at_cursor(&text);
+    }
+
+    //Insert At Cursor
+    //inserts pasted text at the cursor, replacing the selection if there is one, and moves the cursor to the end of the pasted text... 
+    //the text goes in as it is, without any of the key bindings being triggered
+    fn insert_at_cursor(&mut self, text : &str) {
+        self.document_mut().start_undo_group();
+        self.delete_selection();
+        let position = self.cursor_position;
+        self.cursor_position = self.document_mut().insert_str(&position, text);
+        self.document_mut().end_undo_group();
+    }
+
+    //deletes the selected text (leaving the cursor where it started) and returns it
+    fn delete_selection(&mut self) -> String {
+        let (start, end) = match self.selection() {
+            Some(selection) => selection,
+            None => return String::new(),
+        };
+
+        self.selection_anchor = None;
+        self.cursor_position = start;
+        return self.document_mut().delete_range(&start, &end);
+    }
+
+    //------------------------------------------------------------------------//
+    //------------------------------ Vim Mode --------------------------------//
+    //------------------------------------------------------------------------//
+
+    fn in_mode(&self, mode : Mode) -> bool {
+        return self.config.vim_mode == true && self.modal.mode == mode;
+    }
+
+    //Vim Key
+    //handles a key typed in normal or visual mode, returning false if it is not a vim command. Characters are added to the command 
+    //being typed until it is complete, Esc throws the command away (and leaves visual mode) and Ctrl-R redoes like it does in vim.
+    //Backspace moves left like h and Delete deletes like x, while any other key bound to an editing action does nothing, as the text 
+    //is only typed over in insert mode
+    fn vim_key(&mut self, key : Key) -> Result<bool, std::io::Error> {
+        let key = match key {
+            Key::Backspace => Key::Char('h'),
+            Key::Delete => Key::Char('x'),
+            key => key,
+        };
+
+        match key {
+            Key::Esc => {
+                self.modal.clear_pending();
+                if self.modal.mode == Mode::Visual {
+                    self.modal.mode = Mode::Normal;
+                    self.selection_anchor = None;
+                }
+            }
+            Key::Ctrl('r') => {
+                self.run_action(Action::Redo)?;
+            }
+            Key::Char(c) => {
+                if let Some(command) = self.modal.push(c) {
+                    self.vim_command(command)?;
+                }
+            }
+            _ if self.config.keymap.get(key).map(|action| action.is_edit()) == Some(true) => (),
+            _ => return Ok(false),
+        }
+
+        return Ok(true);
+    }
+
+    //Vim Command
+    //carries out a complete command. Changes that do not go into insert mode are finished straight away and become the change "." 
+    //repeats... the ones that do are finished when insert mode is left, so the text typed is part of them
+    fn vim_command(&mut self, command : Command) -> Result<(), std::io::Error> {
+        match command {
+            Command::Move(motion, count) => {
+                self.cursor_position = self.vim_target(motion, count);
+            }
+            Command::Operate(operator, motion, count) => {
+                self.vim_operate(operator, motion, count);
+            }
+            Command::OperateLines(operator, count) => {
+                let y = self.cursor_position.y;
+                self.vim_operate_lines(operator, y, y + count - 1);
+            }
+            Command::OperateSelection(operator) => {
+                if let Some((start, end)) = self.selection() {
+                    self.modal.mode = Mode::Normal;
+                    self.selection_anchor = None;
+                    self.vim_operate_range(operator, start, end);
+                }
+            }
+            Command::DeleteChar(count) => {
+                let Position { x, y } = self.cursor_position;
+                let end = Position { x: cmp::min(x.saturating_add(count), self.document().row_len(y)), y };
+                self.vim_operate_range(Operator::Delete, self.cursor_position, end);
+            }
+            Command::Insert(at) => {
+                self.vim_insert(at);
+            }
+            Command::Paste(before, count) => {
+                self.vim_paste(before, count);
+            }
+            Command::Undo(count) => {
+                for _ in 0..count {
+                    self.run_action(Action::Undo)?;
+                }
+            }
+            Command::Repeat(count) => {
+                let keys = self.modal.last_change();
+                for _ in 0..count {
+                    for key in &keys {
+                        self.handle_key(*key)?;
+                    }
+                }
+            }
+            Command::OpenCommandLine => {
+                self.command_line()?;
+            }
+            Command::ToggleVisual => {
+                if self.modal.mode == Mode::Visual {
+                    self.modal.mode = Mode::Normal;
+                    self.selection_anchor = None;
+                } else {
+                    self.modal.mode = Mode::Visual;
+                    self.selection_anchor = Some(self.cursor_position);
+                }
+            }
+        }
+
+        if command.is_change() == true && self.modal.mode != Mode::Insert {
+            self.modal.finish_change();
+        }
+        if self.modal.mode != Mode::Insert {
+            self.vim_clamp_cursor();
+        }
+        return Ok(());
+    }
+
+    //Vim Target
+    //where the motion takes the cursor. h, j, k and l move the way the arrow key actions do, through move_cursor (so j and k go by 
+    //screen lines when the row is wrapped), except that h and l stay on the row and j and k stop at the last row. l can go as far as 
+    //the end of the row, which an operator needs ("dl" on the last grapheme)... the cursor itself is kept off it by vim_clamp_cursor
+    fn vim_target(&mut self, motion : Motion, count : Option<usize>) -> Position {
+        let action = match motion {
+            Motion::Left => Action::MoveLeft,
+            Motion::Right => Action::MoveRight,
+            Motion::Up => Action::MoveUp,
+            Motion::Down => Action::MoveDown,
+            _ => return modal::target(self.document(), self.cursor_position, motion, count),
+        };
+
+        let from = self.cursor_position;
+        for _ in 0..count.unwrap_or(1) {
+            let before = self.cursor_position;
+            let stays_on_row = match motion {
+                Motion::Left => before.x > 0,
+                Motion::Right => self.document().has_grapheme(before.y, before.x),
+                _ => true,
+            };
+            if stays_on_row == false {
                 break;
             }
 
-            if let Err(error) = self.process_keypress() {
-                self.die(error);
+            self.move_cursor(action);
+            if self.cursor_position == before || self.cursor_position.y >= self.document().len() {
+                self.cursor_position = before;
+                break;
+            }
+        }
+
+        let mut target = self.cursor_position;
+        self.cursor_position = from;
+        //up and down keep the column where the row is long enough
+        if self.document().has_grapheme(target.y, target.x) == false {
+            target.x = cmp::min(target.x, self.document().row_len(target.y));
+        }
+        return target;
+    }
+
+    //in normal and visual mode the cursor is on a grapheme, so it can not go past the last one of a row (only an empty row has the
+    //cursor at its end)
+    fn vim_clamp_cursor(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        if x > 0 && self.document().has_grapheme(y, x) == false {
+            self.cursor_position.x = cmp::min(x, self.document().row_len(y).saturating_sub(1));
+        }
+    }
+
+    //Vim Operate
+    //applies the operator from the cursor to where the motion goes. Motions that move between rows work on whole rows, "cw" changes 
+    //up to the end of the word (not up to the next word), and "dw" on the last word of a row does not join it with the next row
+    fn vim_operate(&mut self, operator : Operator, motion : Motion, count : Option<usize>) {
+        let from = self.cursor_position;
+        let target = self.vim_target(motion, count);
+
+        if motion.is_linewise() == true {
+            self.vim_operate_lines(operator, cmp::min(from.y, target.y), cmp::max(from.y, target.y));
+            return;
+        }
+
+        let (start, mut end) = if (target.y, target.x) < (from.y, from.x) { (target, from) } else { (from, target) };
+        if operator == Operator::Change && motion == Motion::WordForward && modal::is_blank_at(self.document(), from) == false {
+            end = modal::end_of_word(self.document(), from);
+            if count.unwrap_or(1) > 1 {
+                let last = Position { x: end.x.saturating_sub(1), y: end.y };
+                end = modal::next_position(self.document(), modal::target(self.document(), last, Motion::WordEnd, Some(count.unwrap_or(1) - 1)));
+            }
+        } else if motion.is_inclusive() == true && end.x < self.document().row_len(end.y) {
+            end.x = end.x + 1;
+        } else if motion == Motion::WordForward && end.y > start.y {
+            end = Position { x: self.document().row_len(start.y), y: start.y };
+        }
+
+        self.vim_operate_range(operator, start, end);
+    }
+
+    //deletes, changes or yanks the text between two positions. The text goes to the register either way, so it can be put back with p,
+    //but only yanked text goes to the clipboard. An empty range (x on an empty row, d0 at the start of one) leaves the register as it was
+    fn vim_operate_range(&mut self, operator : Operator, start : Position, end : Position) {
+        self.cursor_position = start;
+        let text = self.document().text_range(&start, &end);
+        if text.is_empty() {
+            if operator == Operator::Change {
+                self.enter_insert_mode();
+            }
+            return;
+        }
+        self.modal.register = text.clone();
+        self.modal.linewise = false;
+
+        match operator {
+            Operator::Yank => {
+                self.yank(&text, "Yanked");
+            }
+            Operator::Delete => {
+                self.document_mut().delete_range(&start, &end);
+            }
+            Operator::Change => {
+                self.enter_insert_mode();
+                self.document_mut().delete_range(&start, &end);
+            }
+        }
+    }
+
+    //Vim Operate Lines
+    //applies the operator to the rows from first to last. Deleting the last rows of the document takes the '\n' before them away (so no
+    //empty row is left behind), and changing rows empties them into a single row that insert mode starts on
+    fn vim_operate_lines(&mut self, operator : Operator, first : usize, last : usize) {
+        let len = self.document().len();
+        if len == 0 {
+            if operator == Operator::Change {
+                self.enter_insert_mode();
+            }
+            return;
+        }
+
+        let last = cmp::min(last, len - 1);
+        let row_len = |editor : &Self, y : usize| editor.document().row_len(y);
+        let mut text = self.document().text_range(&Position { x: 0, y: first }, &Position { x: 0, y: last + 1 });
+        if text.ends_with('\n') == false {
+            text.push('\n');
+        }
+        self.modal.register = text.clone();
+        self.modal.linewise = true;
+
+        match operator {
+            Operator::Yank => {
+                self.yank(&text, "Yanked");
+                self.cursor_position = Position { x: self.cursor_position.x, y: first };
             }
+            Operator::Delete => {
+                let (start, end) = if last + 1 < len {
+                    (Position { x: 0, y: first }, Position { x: 0, y: last + 1 })
+                } else if first > 0 {
+                    (Position { x: row_len(self, first - 1), y: first - 1 }, Position { x: row_len(self, last), y: last })
+                } else {
+                    (Position { x: 0, y: 0 }, Position { x: row_len(self, last), y: last })
+                };
+                self.document_mut().delete_range(&start, &end);
+
+                let y = cmp::min(first, self.document().len().saturating_sub(1));
+                self.cursor_position = Position { x: modal::first_non_blank(self.document(), y), y };
+            }
+            Operator::Change => {
+                self.enter_insert_mode();
+                let end = Position { x: row_len(self, last), y: last };
+                self.document_mut().delete_range(&Position { x: 0, y: first }, &end);
+                self.cursor_position = Position { x: 0, y: first };
+            }
+        }
+    }
+
+    fn vim_insert(&mut self, at : InsertAt) {
+        let Position { x, y } = self.cursor_position;
+        let row_len = self.document().row_len(y);
+        self.enter_insert_mode();
+
+        match at {
+            InsertAt::Cursor => (),
+            InsertAt::After => self.cursor_position.x = cmp::min(x + 1, row_len),
+            InsertAt::LineStart => self.cursor_position.x = modal::first_non_blank(self.document(), y),
+            InsertAt::LineEnd => self.cursor_position.x = row_len,
+            InsertAt::NewLineBelow => {
+                if self.document().is_empty() == false {
+                    self.document_mut().insert(&Position { x: row_len, y }, '\n');
+                    self.cursor_position = Position { x: 0, y: y + 1 };
+                }
+            }
+            InsertAt::NewLineAbove => {
+                self.document_mut().insert(&Position { x: 0, y }, '\n');
+                self.cursor_position = Position { x: 0, y };
+            }
+        }
+    }
+
+    //Vim Paste
+    //puts back the text in the register. Text that was yanked or deleted as whole rows is put on the rows below the cursor (or above it, for P), anything else goes after
+    //the cursor (or before it). The cursor ends up on the last pasted grapheme, or at the start of the pasted rows
+    fn vim_paste(&mut self, before : bool, count : usize) {
+        let text = self.modal.register.clone();
+        if text.is_empty() {
+            return;
+        }
+        if text.len().saturating_mul(count) > MAX_PASTE {
+            self.status_message = Status_Message::create(format!("ERR: {} copies of the register are too much to paste at once", count));
+            return;
+        }
+        let linewise = self.modal.linewise == true && text.ends_with('\n');
+        let text = text.repeat(count);
+        let Position { x, y } = self.cursor_position;
+        let len = self.document().len();
+
+        if linewise == true {
+            let y = if before == true { y } else { cmp::min(y + 1, len) };
+            if y < len {
+                self.document_mut().insert_str(&Position { x: 0, y }, &text);
+            } else if len == 0 {
+                self.document_mut().insert_str(&Position { x: 0, y: 0 }, &text[..text.len() - 1]);
+            } else {
+                //after the last row, the '\n' goes before the pasted rows instead of after them
+                let end = Position { x: self.document().row_len(len - 1), y: len - 1 };
+                self.document_mut().insert_str(&end, &format!("\n{}", &text[..text.len() - 1]));
+            }
+            self.cursor_position = Position { x: modal::first_non_blank(self.document(), y), y };
+        } else {
+            let row_len = self.document().row_len(y);
+            let x = if before == true { x } else { cmp::min(x + 1, row_len) };
+            let end = self.document_mut().insert_str(&Position { x, y }, &text);
+            self.cursor_position = Position { x: end.x.saturating_sub(1), y: end.y };
         }
     }
 
+    //everything typed between entering and leaving insert mode is undone in one step, along with the text a change command deleted
+    fn enter_insert_mode(&mut self) {
+        self.modal.mode = Mode::Insert;
+        self.document_mut().start_undo_group();
+        self.modal.open_group = Some(self.current_buffer);
+    }
+
+    fn leave_insert_mode(&mut self) {
+        self.modal.mode = Mode::Normal;
+        self.modal.record(Key::Esc);
+        self.modal.finish_change();
+        if let Some(buffer) = self.modal.open_group.take() {
+            self.buffers[buffer].document.end_undo_group();
+        }
+        self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
+    }
+
+    fn dirty_buffers(&self) -> usize {
+        return self.buffers.iter().filter(|buffer| buffer.document.is_dirty()).count();
+    }
+
     //------------------------------------------------------------------------//
     //-------------------- Screen Management Functions -----------------------//
     //------------------------------------------------------------------------//
@@ -103,78 +1010,194 @@ impl Editor {
     //Welcome Message:
     //This function helps draw the welcome message users see when first opening the text editor (without a document loaded)
     //How this is done is by the following steps:
-    //1. get the width of the terminal
+    //1. get the width of the pane
     //2. get the length of the welcome message
-    //3. to center the welcome message, we must place it such that the middle of the welcome message is in the middle of the terminal
-    //4. the padding required to accomplish step 3 is by subtracting the width of the terminal by the length of the message and dividing by 2 
+    //3. to center the welcome message, we must place it such that the middle of the welcome message is in the middle of the pane
+    //4. the padding required to accomplish step 3 is by subtracting the width of the pane by the length of the message and dividing by 2 
     //   what this does is allow us to have a centered message for *any* message we want as the algorithm to center it will stay the same
-    //5. ensure that the message does not exceed the terminal width by truncating it
-    fn draw_welcome_message(&self) {
+    //5. ensure that the message does not exceed the pane width by truncating it
+    fn draw_welcome_message(&self, width : usize) {
         let mut welcome_message : String = format!("Text Editor -- version {}", VERSION);
-        let width   : usize = self.terminal.size().width as usize;
         let len     : usize = welcome_message.len();
         let padding : usize = width.saturating_sub(len) / 2;
         let spaces  : String = " ".repeat(padding.saturating_sub(1));
         
         welcome_message = format!("~{}{}", spaces, welcome_message);
-        welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+        self.terminal.print(&fit_to_width(&welcome_message, width));
     }
 
     //Draw Row
-    //This function helps print a single row onto the screen based on the terminal size and the row size. Because the length of the string stored in a single row is not
-    //depandant on the size of the terminal, we must *only* print the characters of the row that are visible based on the current position of the terminal (in terms of pan)
-    //This means we must calcuate the current start position (based on the offset), and the current end position (offset + width of terminal), and only print the characters
-    //within that range.
-    fn draw_row(&self, row : &Row) {
-        let width   : usize = self.terminal.size().width as usize;
-        let start   : usize = self.offset.x;
-        let end     : usize = self.offset.x + width;
-        let row     : String = row.render(start, end);
-        println!("{}\r", row);
+    //This function helps print a single row onto the screen based on the pane size and the row size. Because the length of the string stored in a single row is not
+    //depandant on the size of the pane, we must *only* print the characters of the row that are visible based on the current position of the pane (in terms of pan)
+    //This means we must calcuate the current start position (based on the offset), and the current end position (offset + width of pane), and only print the characters
+    //within that range. The offset and the width are screen columns rather than graphemes, as a grapheme can take up more than one
+    //column (a tab, a wide character) or be shown as several (a control character)
+    //Each run of text is drawn in the colour of its highlighting type, and the selected part of the row (the graphemes between the two
+    //ends of `selected`) is drawn on a different background. The runs are read straight from the rope, so only the part of the row up
+    //to the right edge of the pane is walked through
+    fn draw_row(&self, document : &Document, index : usize, (start, end) : (usize, usize), width : usize, selected : Option<(usize, usize)>) {
+        let mut used : usize = 0;
+        for (highlighting_type, is_selected, text) in document.render(index, start, end, self.config.tab_width, selected) {
+            match highlighting_type.to_color() {
+                Some(color) => self.terminal.set_fg_color(color),
+                None => self.terminal.reset_fg_color(),
+            }
+            if is_selected == true {
+                self.terminal.set_bg_color(self.config.colors.selection_bg);
+            }
+            self.terminal.print(&text);
+            self.terminal.reset_bg_color();
+            used = used + text.width();
+        }
+
+        self.terminal.reset_fg_color();
+        self.terminal.print(&" ".repeat(width.saturating_sub(used)));
     }
 
     //Draw Rows
-    //what this does is take the draw row concept and apply it to the entire terminal (height wise)... 
-    //it starts at the top of the terminal and makes its way down, drawing a line on on each available line on the terminal
-    //it also takes into accound the current y offset (page offset) so that as a user scrolls, they see the correct data on 
-    //terminal. First, the line is cleared, and then the new information is printed... the line to be printed is determined by
-    //indexing the document vector at the specified y offset + cursor position 
-    fn draw_rows(&self) {
-        let height  = self.terminal.size().height;
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self.document.row(terminal_row as usize + self.offset.y) {
-                self.draw_row(row);
-            } else if self.document.is_empty() == true && terminal_row == height / 3 {
-                self.draw_welcome_message();
+    //what this does is take the draw row concept and apply it to the entire pane (height wise)... 
+    //it starts at the top of the pane and makes its way down, drawing a line on on each available line of the pane
+    //it also takes into accound the pane's y offset (page offset) so that as a user scrolls, they see the correct data on 
+    //terminal. Each line is printed at the pane's position and padded to the pane's width, which overwrites whatever was there before... 
+    //the line to be printed is determined by the screen lines of the pane, which start at its offset (a wrapped row takes up several of
+    //them). Each row of the document starts with its part of the gutter, and the text gets whatever width is left
+    fn draw_rows(&self, buffer : usize, offset : &Position, area : Rect, selection : Option<(Position, Position)>, cursor_row : usize) {
+        let document = &self.buffers[buffer].document;
+        let gutter = self.gutter(document);
+        let gutter_width = cmp::min(gutter.width(), area.width);
+        let width = area.width - gutter_width;
+        let lines = self.screen_lines(buffer, offset, width, area.height);
+
+        for terminal_row in 0..area.height {
+            self.terminal.cursor_position(&Position { x: area.x, y: area.y + terminal_row });
+            if let Some(line) = lines.get(terminal_row) {
+                let index = line.row;
+                //the part of this row that is selected... rows between the two ends are selected as a whole
+                let selected = selection.filter(|(start, end)| start.y <= index && index <= end.y).map(|(start, end)| {
+                    let from = if start.y == index { start.x } else { 0 };
+                    let to = if end.y == index { end.x } else { document.row_len(index) };
+                    (from, to)
+                });
+                let gutter_text = if line.first == true { gutter.render(index, cursor_row) } else { String::new() };
+                self.terminal.set_fg_color(self.config.colors.line_number_fg);
+                self.terminal.print(&fit_to_width(&gutter_text, gutter_width));
+                self.terminal.reset_fg_color();
+                self.draw_row(document, index, (line.start, line.end), width, selected);
+            } else if document.is_empty() == true && terminal_row == area.height / 3 {
+                self.draw_welcome_message(area.width);
+            } else {
+                self.terminal.print(&fit_to_width("~", area.width));
+            }
+        }
+    }
+
+    //Screen Lines
+    //what each line of a pane's text area shows, from the top down to the last row of the document. Without wrapping, every line shows
+    //a whole row from the horizontal offset on... with it, each row takes as many lines as it needs, and offset.x is the number of 
+    //lines of the top row that are scrolled out of the view instead
+    fn screen_lines(&self, buffer : usize, offset : &Position, width : usize, height : usize) -> Vec<ScreenLine> {
+        let Buffer { document, wrap, .. } = &self.buffers[buffer];
+        let mut lines : Vec<ScreenLine> = Vec::new();
+        let mut index = offset.y;
+
+        //a row is only wrapped if wrapping is on
+        while lines.len() < height && index < document.len() {
+            if *wrap == false {
+                lines.push(ScreenLine { row: index, start: offset.x, end: offset.x + width, first: true });
             } else {
-                println!("~\r");
+                let row = self.wrap_row(buffer, index, width);
+                let skip = if index == offset.y { offset.x } else { 0 };
+                for (line, (_, start)) in row.lines.iter().enumerate().skip(skip) {
+                    let end = row.lines.get(line + 1).map(|(_, column)| *column).unwrap_or(row.width);
+                    lines.push(ScreenLine { row: index, start: *start, end, first: line == 0 });
+                }
             }
+            index = index + 1;
+        }
+
+        lines.truncate(height);
+        return lines;
+    }
+
+    fn is_wrapped(&self) -> bool {
+        return self.buffers[self.current_buffer].wrap;
+    }
+
+    //Wrap Row
+    //wraps a row of the buffer to the given width. While a frame is being drawn every wrapped row is kept until the frame is done, so
+    //that a row is only built and wrapped once per frame however many times it is drawn or the cursor is placed on it
+    fn wrap_row(&self, buffer : usize, index : usize, width : usize) -> Rc<WrappedRow> {
+        let key = (buffer, index, width);
+        if let Some(row) = self.wrapped.borrow().as_ref().and_then(|wrapped| wrapped.get(&key)) {
+            return Rc::clone(row);
+        }
+
+        let row = self.buffers[buffer].document.row(index).unwrap_or_default();
+        let wrapped_row = Rc::new(WrappedRow {
+            lines   : row.wrap(width, self.config.tab_width),
+            width   : row.width(self.config.tab_width),
+        });
+        if let Some(wrapped) = self.wrapped.borrow_mut().as_mut() {
+            wrapped.insert(key, Rc::clone(&wrapped_row));
         }
+        return wrapped_row;
+    }
+
+    //where each line of the row starts when it is wrapped in the focused pane (a row past the end of the document has one empty line)
+    fn wrapped_lines(&self, y : usize) -> Vec<(usize, usize)> {
+        return self.wrap_row(self.current_buffer, y, self.text_area().width).lines.clone();
+    }
+
+    //which of the wrapped lines of its row the cursor is on
+    fn cursor_line(&self) -> usize {
+        let Position { x, y } = self.cursor_position;
+        return self.wrapped_lines(y).iter().rposition(|(start, _)| *start <= x).unwrap_or(0);
+    }
+
+    //Cursor On Screen
+    //where the cursor is drawn, counting from the top left of the text area of its pane
+    fn cursor_on_screen(&self) -> Position {
+        let y = self.cursor_position.y;
+        let column = self.cursor_column();
+        if self.is_wrapped() == false {
+            return Position { x: column.saturating_sub(self.offset.x), y: y.saturating_sub(self.offset.y) };
+        }
+
+        let line = self.cursor_line();
+        let lines_above : usize = (self.offset.y..y).map(|index| self.wrapped_lines(index).len()).sum();
+        return Position {
+            x: column.saturating_sub(self.wrapped_lines(y)[line].1),
+            y: (lines_above + line).saturating_sub(self.offset.x),
+        };
     }
 
     //Draw Status Bar
-    //This function draws the status bar at the bottom of the terminal which displays the file-name, dirty status, total lines, and the current line
+    //This function draws the status bar at the bottom of each pane which displays the buffer number, file-name, dirty status, total lines, file type, and the current line
     //We want the file name, dirty status, and total lines are on the left side vs the current line number on the right. To achieve this we need to 
     //find the length of the left hand string, and then the right hand string, and add padding spaces in between in order to seperate the two.
-    //We will also set the background color of the status bar to white in order to make it clear to the user that it is not part of the editor
-    fn draw_status_bar(&self) {
+    //We will also set the background color of the status bar to white in order to make it clear to the user that it is not part of the editor... the
+    //status bars of the panes that do not have the focus are drawn in darker colours
+    fn draw_status_bar(&self, buffer : usize, cursor_position : &Position, area : Rect, focused : bool) {
         //let spaces = " ".repeat(self.terminal.size().width as usize);
+        let document            : &Document = &self.buffers[buffer].document;
         let mut status          : String;
-        let width               : usize = self.terminal.size().width as usize;
-        let modified_indicator  : &str = if self.document.is_dirty() == true { "(modified)" } else { "(up to date)" };
-        let line_indicator      : String = format!("{}/{}", self.cursor_position.y.saturating_add(1), self.document.len());
+        let width               : usize = area.width;
+        let modified_indicator  : &str = if document.is_dirty() == true { "(modified)" } else { "(up to date)" };
+        let format              = document.format();
+        let bom_indicator       : &str = if format.bom == true { " BOM" } else { "" };
+        let line_indicator      : String = format!("{} | {}{} | {}/{}", document.file_type().name(), format.line_ending.name(), bom_indicator, cursor_position.y.saturating_add(1), document.len());
         let mut file_name       : String = "[No Name]".to_string();
 
         /*I don't fully get why its structured like this... */
-        if let Some(name) = &self.document.file_name {
-            file_name = name.clone();
-            file_name.truncate(20);
+        if let Some(name) = &document.file_name {
+            file_name = name.graphemes(true).take(20).collect();
         }
 
-        status = format!("{} - {} lines {}", file_name, self.document.len(), modified_indicator);
-        let len : usize = status.len() + line_indicator.len();
+        status = format!("[{}/{}] {} - {} lines {}", buffer + 1, self.buffers.len(), file_name, document.len(), modified_indicator);
+        if self.config.vim_mode == true && focused == true {
+            status = format!("-- {} -- {}", self.modal.mode.name(), status);
+        }
+        let len : usize = status.width() + line_indicator.width();
 
         /*I don't fully get why its structured like this -> why do we need the & for the push string method */
         if width > len {
@@ -182,48 +1205,96 @@ impl Editor {
         }
 
         status = format!("{}{}", status, line_indicator);
-        status.truncate(width);
 
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        println!("{}\r", status);
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
+        if focused == true {
+            self.terminal.set_fg_color(self.config.colors.status_fg);
+            self.terminal.set_bg_color(self.config.colors.status_bg);
+        } else {
+            self.terminal.set_fg_color(self.config.colors.inactive_status_fg);
+            self.terminal.set_bg_color(self.config.colors.inactive_status_bg);
+        }
+        self.terminal.cursor_position(&Position { x: area.x, y: area.y });
+        self.terminal.print(&fit_to_width(&status, width));
+        self.terminal.reset_fg_color();
+        self.terminal.reset_bg_color();
+    }
+
+    //Draw Pane
+    //a pane is drawn as the rows of its document followed by its own status bar on the last line of its area
+    fn draw_pane(&self, pane : usize, area : Rect) {
+        let (buffer, cursor_position, offset) = self.pane_view(pane);
+        let text_area = Rect { height: area.height.saturating_sub(1), ..area };
+        let status_area = Rect { y: area.y + text_area.height, height: 1, ..area };
+
+        let selection = if pane == self.current_pane { self.selection() } else { None };
+        self.draw_rows(buffer, &offset, text_area, selection, cursor_position.y);
+        if area.height > 0 {
+            self.draw_status_bar(buffer, &cursor_position, status_area, pane == self.current_pane);
+        }
     }
 
     //Draw Message Bar
     //this function draws the HELP message bar when the editor is first started... the message bar is removed after typing (in around 5 seconds) 
     fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
+        self.terminal.cursor_position(&Position { x: 0, y: self.terminal.size().height as usize });
+        self.terminal.clear_current_line();
         let message = &self.status_message;
 
-        if Instant::now() - message.time < Duration::new(5, 0) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+        if Instant::now() - message.time < self.config.message_timeout {
+            self.terminal.print(&fit_to_width(&message.text, self.terminal.size().width as usize));
         }
     }
 
     //Refresh Screen
     //This function "renders" the screen... essentially the screen you see is not a static until something happens, instead it is continously refreshed
-    //even if nothing changes. Thus it draws all the rows of the terminal, followed by the status bar, followed by the message bar... while it is drawing the
-    //rows, it hides the cursor, and once it has finished drawing everything it displays the cursor again... this cursor off-on feature is not noticable however
-    fn refresh_screen(&self) -> Result<(), std::io::Error> {
-        Terminal::cursor_hide();
-        Terminal::cursor_position(&Position::default());
+    //even if nothing changes. Thus it draws every pane (its rows followed by its status bar) and the separators between them, followed by the message bar... 
+    //while it is drawing, it hides the cursor, and once it has finished drawing everything it displays the cursor again in the pane that has the focus... 
+    //this cursor off-on feature is not noticable however. On the alternate screen there is nothing left to draw once the user quits, as
+    //leaving it brings back whatever was on the terminal before the editor started
+    fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
+        if self.should_quit == true && self.config.alternate_screen == true {
+            return Ok(());
+        }
+        self.update_highlighting();
+        //the document cannot change while the frame is drawn, so its wrapped rows are kept until the frame is done
+        *self.wrapped.borrow_mut() = Some(HashMap::new());
+        self.terminal.cursor_hide();
+        self.terminal.cursor_position(&Position::default());
 
         if self.should_quit == true {
-            Terminal::clear_screen();
-            println!("Goodbye. \r");
+            self.terminal.clear_screen();
+            self.terminal.print("Goodbye.");
+            self.terminal.cursor_position(&Position { x: 0, y: 1 });
         } else {
-            self.draw_rows();
-            self.draw_status_bar();
+            for (pane, area) in self.layout.areas(self.screen_area()) {
+                self.draw_pane(pane, area);
+            }
+            for separator in self.layout.separators(self.screen_area()) {
+                for y in separator.y..separator.y + separator.height {
+                    self.terminal.cursor_position(&Position { x: separator.x, y });
+                    self.terminal.print("|");
+                }
+            }
             self.draw_message_bar();
-            Terminal::cursor_position(&Position { x: self.cursor_position.x.saturating_sub(self.offset.x), y: self.cursor_position.y.saturating_sub(self.offset.y) });
+
+            let area = self.text_area();
+            let cursor = self.cursor_on_screen();
+            self.terminal.cursor_position(&Position { x: area.x + cursor.x, y: area.y + cursor.y });
         }
+        *self.wrapped.borrow_mut() = None;
+
+        self.terminal.cursor_show();
+        return self.terminal.flush();
+    }
 
-        Terminal::cursor_show();
-        return Terminal::flush();
+    //Update Highlighting
+    //highlights each document down to the last row that one of its panes shows, as the highlighting of a row depends on every row
+    //above it
+    fn update_highlighting(&mut self) {
+        for (pane, area) in self.layout.areas(self.screen_area()) {
+            let (buffer, _, offset) = self.pane_view(pane);
+            self.buffers[buffer].document.highlight(offset.y + area.height);
+        }
     }
 
     //Scroll
@@ -233,12 +1304,20 @@ impl Editor {
     //on the terminal, an "offset" is calculated based on the cursor position... if the cursor is moved passed the boundry of the terminal, the offset is update 
     //and the rows rendered on the terminal changed (as we moved the cursor down, the terminal appears to scroll - this is caused by the fact that the rows being renderd
     //change)  
+    //The horizontal offset is in screen columns, so it is worked out from the column the cursor is in rather than from its grapheme index
     fn scroll(&mut self) {
-        let Position { x, y} = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
+        if self.is_wrapped() == true {
+            self.scroll_wrapped();
+            return;
+        }
 
-        let mut offset = &mut self.offset;
+        let y = self.cursor_position.y;
+        let x = self.cursor_column();
+        let area = self.text_area();
+        let width = area.width;
+        let height = area.height;
+
+        let offset = &mut self.offset;
         
         if y < offset.y {
             offset.y = y;
@@ -253,21 +1332,402 @@ impl Editor {
         }
     }
 
+    //Scroll Wrapped
+    //with wrapping on, the view moves by screen lines: offset.y is the row at the top and offset.x the number of its lines above the 
+    //view. If the cursor's line is below the view, the view is moved down until that line is the last one in it
+    fn scroll_wrapped(&mut self) {
+        let y = self.cursor_position.y;
+        let line = self.cursor_line();
+        let height = self.text_area().height;
+
+        self.offset.x = cmp::min(self.offset.x, self.wrapped_lines(self.offset.y).len() - 1);
+        if (y, line) < (self.offset.y, self.offset.x) {
+            self.offset = Position { x: line, y };
+            return;
+        }
+        //every row takes at least one line, so this only has to be counted when the cursor is close to the view
+        if y - self.offset.y < height {
+            let lines_above : usize = (self.offset.y..y).map(|index| self.wrapped_lines(index).len()).sum();
+            if lines_above + line - self.offset.x < height {
+                return;
+            }
+        }
+
+        let (mut top, mut top_line) = (y, line);
+        for _ in 1..height {
+            if top_line > 0 {
+                top_line = top_line - 1;
+            } else if top > 0 {
+                top = top - 1;
+                top_line = self.wrapped_lines(top).len() - 1;
+            } else {
+                break;
+            }
+        }
+        self.offset = Position { x: top_line, y: top };
+    }
+
+    //Move Screen Line
+    //with wrapping on, up and down move to the line above or below on the screen (which can be part of the same row), keeping to the 
+    //same column of the line where it is long enough
+    fn move_screen_line(&mut self, down : bool) {
+        let y = self.cursor_position.y;
+        let line = self.cursor_line();
+        let column = self.cursor_column() - self.wrapped_lines(y)[line].1;
+
+        let (y, line) = if down == true {
+            if line + 1 < self.wrapped_lines(y).len() {
+                (y, line + 1)
+            } else if y < self.document().len() {
+                (y + 1, 0)
+            } else {
+                return;
+            }
+        } else if line > 0 {
+            (y, line - 1)
+        } else if y > 0 {
+            (y - 1, self.wrapped_lines(y - 1).len() - 1)
+        } else {
+            return;
+        };
+
+        let lines = self.wrapped_lines(y);
+        let (start, start_column) = lines[line];
+        let end = match lines.get(line + 1) {
+            Some((next, _)) => next - 1,
+            None => self.document().row_len(y),
+        };
+        let x = self.document().index_at(y, start_column + column, self.config.tab_width);
+        self.cursor_position = Position { x: cmp::max(start, cmp::min(x, end)), y };
+    }
+
+    //------------------------------------------------------------------------//
+    //-------------------------------- Mouse ---------------------------------//
+    //------------------------------------------------------------------------//
+    //Handle Mouse
+    //a click on a pane's text moves the cursor there (focusing that pane first), and dragging from it selects the text in between (in
+    //visual mode, in vim mode)... dragging past the top or bottom of the pane goes on to the line beyond it, so the view follows. The 
+    //wheel scrolls the pane under the mouse, and a click on a pane's status bar focuses it (or on its buffer number, "[1/3]", also 
+    //moves on to the next buffer)
+    fn handle_mouse(&mut self, event : MouseEvent) {
+        let (column, line) = match event {
+            MouseEvent::Press(_, x, y) | MouseEvent::Hold(x, y) | MouseEvent::Release(x, y) => {
+                (x.saturating_sub(1) as usize, y.saturating_sub(1) as usize)
+            }
+        };
+
+        match event {
+            MouseEvent::Press(MouseButton::Left, ..) => {
+                self.mouse_anchor = None;
+                let area = match self.focus_pane_at(column, line) {
+                    Some(area) => area,
+                    None => return,
+                };
+                if line == area.y + area.height - 1 {
+                    let vim_prefix = if self.config.vim_mode == true { format!("-- {} -- ", self.modal.mode.name()).width() } else { 0 };
+                    let indicator = format!("[{}/{}]", self.current_buffer + 1, self.buffers.len()).width();
+                    if (vim_prefix..vim_prefix + indicator).contains(&(column - area.x)) {
+                        self.next_buffer();
+                    }
+                    return;
+                }
+
+                self.modal.clear_pending();
+                if self.modal.mode == Mode::Visual {
+                    self.modal.mode = Mode::Normal;
+                }
+                self.selection_anchor = None;
+                self.cursor_position = self.position_at(column, line);
+                self.mouse_anchor = Some(self.cursor_position);
+            }
+            MouseEvent::Hold(..) => {
+                let anchor = match self.mouse_anchor {
+                    Some(anchor) => anchor,
+                    None => return,
+                };
+                if self.selection_anchor.is_none() {
+                    self.selection_anchor = Some(anchor);
+                    if self.in_mode(Mode::Normal) == true {
+                        self.modal.mode = Mode::Visual;
+                    }
+                }
+
+                let area = self.text_area();
+                let last_line = area.y + area.height.saturating_sub(1);
+                self.cursor_position = self.position_at(column, cmp::min(cmp::max(line, area.y), last_line));
+                if line < area.y {
+                    self.move_cursor(Action::MoveUp);
+                } else if line > last_line {
+                    self.move_cursor(Action::MoveDown);
+                }
+            }
+            MouseEvent::Release(..) => {
+                self.mouse_anchor = None;
+            }
+            MouseEvent::Press(button @ (MouseButton::WheelUp | MouseButton::WheelDown), ..) => {
+                if self.focus_pane_at(column, line).is_some() {
+                    self.scroll_view(button == MouseButton::WheelDown);
+                }
+            }
+            MouseEvent::Press(..) => (),
+        }
+    }
+
+    //focuses the pane at a point of the screen, returning its area (there is none on the message bar or a separator)
+    fn focus_pane_at(&mut self, column : usize, line : usize) -> Option<Rect> {
+        let (pane, area) = self.layout.areas(self.screen_area()).into_iter().find(|(_, area)| area.contains(column, line))?;
+        if pane != self.current_pane {
+            self.focus_pane(pane);
+        }
+        return Some(area);
+    }
+
+    //Position At
+    //the place in the focused pane's document shown at a point of its text area. A point in the gutter is the first grapheme shown of
+    //the line, a point past the end of the line is the end of it, and a point below the last row is the end of the last row. In vim's
+    //normal and visual mode, the cursor stays on the last grapheme of a line rather than after it
+    fn position_at(&self, column : usize, line : usize) -> Position {
+        let area = self.text_area();
+        let lines = self.screen_lines(self.current_buffer, &self.offset, area.width, area.height);
+        let screen_line = match lines.get(line.saturating_sub(area.y)).or(lines.last()) {
+            Some(screen_line) => screen_line,
+            None => return Position::default(),
+        };
+
+        let tab_width = self.config.tab_width;
+        let document = self.document();
+        let y = screen_line.row;
+        let target = screen_line.start + column.saturating_sub(area.x);
+        let row_len = document.row_len(y);
+        let x = if line >= area.y + lines.len() || (target >= screen_line.end && screen_line.end >= document.width(y, tab_width)) {
+            row_len
+        } else {
+            document.index_at(y, cmp::min(target, screen_line.end.saturating_sub(1)), tab_width)
+        };
+
+        let on_last_grapheme = self.in_mode(Mode::Normal) == true || self.in_mode(Mode::Visual) == true;
+        let x = if on_last_grapheme == true { cmp::min(x, row_len.saturating_sub(1)) } else { x };
+        return Position { x, y };
+    }
+
+    //Scroll View
+    //moves the focused pane's view a few lines (for the mouse wheel) without moving the cursor, unless it would be left outside of 
+    //the view... then it goes to the nearest line that is still in it, so scrolling does not bring the view straight back to it
+    fn scroll_view(&mut self, down : bool) {
+        let wrapped = self.is_wrapped();
+        let len = self.document().len();
+        for _ in 0..WHEEL_LINES {
+            let Position { x, y } = self.offset;
+            self.offset = match (down, wrapped) {
+                (true, true) if x + 1 < self.wrapped_lines(y).len() => Position { x: x + 1, y },
+                (true, _) if y + 1 < len => Position { x: if wrapped == true { 0 } else { x }, y: y + 1 },
+                (false, true) if x > 0 => Position { x: x - 1, y },
+                (false, true) if y > 0 => Position { x: self.wrapped_lines(y - 1).len() - 1, y: y - 1 },
+                (false, false) => Position { x, y: y.saturating_sub(1) },
+                _ => self.offset,
+            };
+        }
+
+        let area = self.text_area();
+        let cursor = self.cursor_position;
+        let above = if wrapped == true {
+            (cursor.y, self.cursor_line()) < (self.offset.y, self.offset.x)
+        } else {
+            cursor.y < self.offset.y
+        };
+        if above == true {
+            self.cursor_position = self.position_at(area.x + self.cursor_on_screen().x, area.y);
+        } else if self.cursor_on_screen().y >= area.height {
+            self.cursor_position = self.position_at(area.x + self.cursor_on_screen().x, area.y + area.height.saturating_sub(1));
+        }
+    }
+
+    //Go To Line
+    //asks for a line, or a line and a column ("120:5"), and goes there
+    fn go_to_line(&mut self) -> Result<(), std::io::Error> {
+        let text = match self.prompt("Go to line[:column]: ", |_, _, _| {})? {
+            Some(text) if text.trim().is_empty() == false => text,
+            _ => return Ok(()),
+        };
+
+        match command::parse_location(&text) {
+            Some((line, column)) => self.go_to(line, column),
+            None => self.status_message = Status_Message::create(format!("ERR: not a line number: {}", text)),
+        }
+        return Ok(());
+    }
+
+    //Go To
+    //moves the cursor to the line and column (both starting from 1, and kept inside the document) and scrolls so that line is in the 
+    //middle of the view
+    fn go_to(&mut self, line : usize, column : Option<usize>) {
+        let y = cmp::min(line.saturating_sub(1), self.document().len().saturating_sub(1));
+        let row_len = self.document().row_len(y);
+        let x = cmp::min(column.unwrap_or(1).saturating_sub(1), row_len);
+
+        self.selection_anchor = None;
+        self.cursor_position = Position { x, y };
+        self.offset = Position { x: 0, y: y.saturating_sub(self.text_area().height / 2) };
+        self.scroll();
+    }
+
+    //the screen column of the cursor in its row, counting from the start of the row (not from the left of the view)
+    fn cursor_column(&self) -> usize {
+        let Position { x, y } = self.cursor_position;
+        return self.document().column(y, x, self.config.tab_width);
+    }
+
+    //Search
+    //the cursor jumps to the first match as the query is typed. The arrow keys step forwards (right/down) or backwards (left/up) through
+    //the matches, wrapping around at either end of the document. If the search is cancelled, the cursor and the view are put back where 
+    //they were before the search started
+    fn search(&mut self) {
+        let old_position = self.cursor_position;
+        let old_offset = self.offset;
+        let mut direction = SearchDirection::Forward;
+
+        let query = self.prompt("Search (ESC to cancel, Arrows to navigate): ", |editor, key, query| {
+            let mut moved = false;
+            match key {
+                Key::Right | Key::Down => {
+                    direction = SearchDirection::Forward;
+                    editor.move_cursor(Action::MoveRight);
+                    moved = true;
+                }
+                Key::Left | Key::Up => direction = SearchDirection::Backward,
+                _ => direction = SearchDirection::Forward,
+            }
+
+            if let Some(position) = editor.document().find(query, &editor.cursor_position, direction) {
+                editor.cursor_position = position;
+                editor.scroll();
+            } else if moved == true {
+                editor.move_cursor(Action::MoveLeft);
+            }
+        }).unwrap_or(None);
+
+        if query.unwrap_or_default().is_empty() {
+            self.cursor_position = old_position;
+            self.offset = old_offset;
+            self.scroll();
+        }
+    }
+
+    //Replace
+    //asks for a regular expression and a replacement (which may refer to capture groups, e.g. $1 or ${name}), then walks through the 
+    //matches from the top of the document. For each match the user can replace it (y), skip it (n), replace it and every match after it
+    //(a) or stop (q / ESC). The whole command is undone as a single step. The terminal failing part way through still closes the undo
+    //group before the error is passed on
+    fn replace(&mut self) -> Result<(), std::io::Error> {
+        let pattern = match self.prompt("Replace (regex): ", |_, _, _| {})? {
+            Some(pattern) if pattern.is_empty() == false => pattern,
+            _ => {
+                self.status_message = Status_Message::create("Replace aborted.".to_string());
+                return Ok(());
+            }
+        };
+
+        let regex = match Regex::new(&pattern) {
+            Ok(regex) => regex,
+            Err(error) => {
+                //the last line of the error says what is wrong, the ones before it point at where in the pattern
+                let reason = error.to_string().lines().last().unwrap_or_default().trim_start_matches("error: ").to_string();
+                self.status_message = Status_Message::create(format!("ERR: invalid pattern {}: {}", pattern, reason));
+                return Ok(());
+            }
+        };
+
+        let replacement = match self.prompt("Replace with: ", |_, _, _| {})? {
+            Some(replacement) => replacement,
+            None => {
+                self.status_message = Status_Message::create("Replace aborted.".to_string());
+                return Ok(());
+            }
+        };
+
+        let mut y = 0;
+        let mut replace_all = false;
+        let mut count = 0;
+        let mut stopped = false;
+        let mut failed = None;
+
+        self.document_mut().start_undo_group();
+        'rows: while y < self.document().len() {
+            //the matches of a row are all found in one go, before any of them is replaced. Replacing one moves the text after it, so
+            //the rest of the matches are found from where the replaced text ends: what was column from is now at origin
+            let matches = self.document().find_regex(&regex, y, &replacement);
+            let mut origin = Position { x: 0, y };
+            let mut from = 0;
+
+            for (x, len, text) in matches {
+                let at = Position { x: origin.x + x - from, y: origin.y };
+                self.cursor_position = at;
+
+                //any other key asks again
+                loop {
+                    let key = if replace_all == true {
+                        Key::Char('a')
+                    } else {
+                        self.scroll();
+                        self.status_message = Status_Message::create("Replace this match? (y)es / (n)o / (a)ll / (q)uit".to_string());
+                        match self.refresh_screen().and_then(|()| self.terminal.read_key()) {
+                            Ok(key) => key,
+                            Err(error) => {
+                                failed = Some(error);
+                                break 'rows;
+                            }
+                        }
+                    };
+
+                    match key {
+                        Key::Char('y') | Key::Char('a') => {
+                            replace_all = key == Key::Char('a');
+                            origin = self.document_mut().replace(&at, len, &text);
+                            from = x + len;
+                            count = count + 1;
+                            break;
+                        }
+                        Key::Char('n') => break,
+                        Key::Char('q') | Key::Esc => {
+                            stopped = true;
+                            break 'rows;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
+            y = origin.y + 1;
+        }
+        self.document_mut().end_undo_group();
+        self.scroll();
+
+        if let Some(error) = failed {
+            return Err(error);
+        }
+        let message = if stopped == true {
+            format!("Replace stopped after {} occurrence(s).", count)
+        } else {
+            format!("Replaced {} occurrence(s).", count)
+        };
+        self.status_message = Status_Message::create(message);
+        return Ok(());
+    }
+
     fn save(&mut self) {
-        if self.document.file_name.is_none() {
-            let new_name = self.prompt("Save as: ").unwrap_or(None);
+        if self.document().file_name.is_none() {
+            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None).filter(|name| name.is_empty() == false);
             if new_name.is_none() {
                 self.status_message = Status_Message::create("Save aborted.".to_string());
                 return;
             }
 
-            self.document.file_name = new_name;
+            self.document_mut().file_name = new_name;
         }
 
-        if self.document.save().is_ok() {
-            self.status_message = Status_Message::create("File saved successfully".to_string());
-        } else {
-            self.status_message = Status_Message::create("Error writing file!".to_string())
+        match self.document_mut().save() {
+            Ok(()) => self.status_message = Status_Message::create("File saved successfully".to_string()),
+            Err(error) => self.status_message = Status_Message::create(format!("Error writing file: {}", error)),
         }
     }
 
@@ -275,134 +1735,374 @@ impl Editor {
     //-------------- Detect And Process Key Pressed --------------------------//
     //------------------------------------------------------------------------//
     fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
-        match pressed_key {
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Alt('k'));
+        let pressed_key = match self.terminal.read_event()? {
+            Event::Key(key) => key,
+            Event::Mouse(mouse) => {
+                self.handle_mouse(mouse);
+                self.scroll();
+                return Ok(());
             }
-            Key::Backspace => {
-                if self.cursor_position.y > 0 || self.cursor_position.x > 0 {
-                    self.move_cursor(Key::Alt('j'));
-                    self.document.delete(&self.cursor_position);
+            Event::Paste(text) => {
+                self.insert_at_cursor(&text);
+                self.scroll();
+                return Ok(());
+            }
+            //the panes are laid out again for the new size when the screen is redrawn, but the cursor may now be outside of the view
+            Event::Resize => {
+                self.scroll();
+                return Ok(());
+            }
+            Event::Tick => return Ok(()),
+        };
+        self.handle_key(pressed_key)?;
+        self.scroll();
+
+        if self.config.keymap.get(pressed_key) != Some(Action::Quit) && self.quit_times < self.config.quit_times {
+            self.quit_times = self.config.quit_times;
+            self.status_message = Status_Message::create(String::new());
+        }
+        return Ok(());
+    }
+
+    //Handle Key
+    //in vim mode, keys typed in normal and visual mode are vim commands first... anything that is not (control and alt keys) goes through
+    //the key bindings like it does outside of vim mode
+    fn handle_key(&mut self, key : Key) -> Result<(), std::io::Error> {
+        if self.config.vim_mode == true {
+            match self.modal.mode {
+                Mode::Insert if key == Key::Esc => {
+                    self.leave_insert_mode();
+                    return Ok(());
+                }
+                Mode::Insert => self.modal.record(key),
+                Mode::Normal | Mode::Visual => {
+                    if self.vim_key(key)? == true {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        match self.config.keymap.get(key) {
+            Some(action) => self.run_action(action)?,
+            None => {
+                if let Key::Char(c) = key {
+                    self.type_char(c);
                 }
             }
-            Key::Delete => {
-                if self.cursor_position.x > 0 || self.cursor_position.y >= 0 {
-                    self.document.delete(&self.cursor_position);
+        }
+        return Ok(());
+    }
+
+    fn type_char(&mut self, c : char) {
+        if self.selection().is_some() {
+            //typing over a selection replaces it, as a single undo step
+            self.document_mut().start_undo_group();
+            self.delete_selection();
+            let position = self.cursor_position;
+            self.document_mut().insert(&position, c);
+            self.document_mut().end_undo_group();
+        } else {
+            let position = self.cursor_position;
+            self.document_mut().insert(&position, c);
+        }
+        self.move_cursor(Action::MoveRight);
+    }
+
+    //Run Action
+    //does whatever the action bound to the pressed key is
+    fn run_action(&mut self, action : Action) -> Result<(), std::io::Error> {
+        match action {
+            Action::DeleteBackward | Action::DeleteForward if self.selection().is_some() => {
+                self.delete_selection();
+            }
+            Action::DeleteBackward => {
+                if self.cursor_position.y > 0 || self.cursor_position.x > 0 {
+                    self.move_cursor(Action::MoveLeft);
+                    let position = self.cursor_position;
+                    self.document_mut().delete(&position);
                 }
             }
-            Key::Alt('q') => {
-                if self.quit_times > 0 && self.document.is_dirty() == true {
-                    self.status_message = Status_Message::create(format!("WARNING! File has unsaved changes. Press Alt-Q {} more times to quit.", self.quit_times));
+            Action::DeleteForward => {
+                let position = self.cursor_position;
+                self.document_mut().delete(&position);
+            }
+            Action::Quit => {
+                let dirty_buffers = self.dirty_buffers();
+                if self.quit_times > 0 && dirty_buffers > 0 {
+                    let key = self.config.keymap.key_for(Action::Quit).map(keymap::key_name).unwrap_or_default();
+                    self.status_message = Status_Message::create(format!("WARNING! {} file(s) have unsaved changes. Press {} {} more times to quit.", dirty_buffers, key, self.quit_times));
                     self.quit_times = self.quit_times - 1;
                     return Ok(());
                 }
 
                 self.should_quit = true;
             }
-            Key::Ctrl('w') => {
+            Action::Save => {
                 self.save();
             }
-            Key::Alt('f') 
-            | Key::Alt('d') 
-            | Key::Alt('j') 
-            | Key::Alt('k') 
-            | Key::Alt('h')
-            | Key::Alt('l')
-            | Key::Alt('v')
-            | Key::Alt('n') => self.move_cursor(pressed_key),
-            _ => (),
+            Action::OpenFile => {
+                self.open_file()?;
+            }
+            Action::NextBuffer => {
+                self.next_buffer();
+            }
+            Action::PreviousBuffer => {
+                self.previous_buffer();
+            }
+            Action::WindowCommand => {
+                self.window_command()?;
+            }
+            Action::Find => {
+                self.search();
+            }
+            Action::Replace => {
+                self.replace()?;
+            }
+            Action::ToggleLineEnding => {
+                let line_ending = match self.document().format().line_ending {
+                    LineEnding::Lf   => LineEnding::CrLf,
+                    LineEnding::CrLf => LineEnding::Lf,
+                };
+                self.document_mut().set_line_ending(line_ending);
+                self.status_message = Status_Message::create(format!("Line endings converted to {}.", line_ending.name()));
+            }
+            Action::SetMark => {
+                self.selection_anchor = Some(self.cursor_position);
+                self.status_message = Status_Message::create("Mark set.".to_string());
+            }
+            Action::ClearSelection => {
+                self.selection_anchor = None;
+            }
+            Action::Copy => {
+                self.copy_selection();
+            }
+            Action::Cut => {
+                self.cut_selection();
+            }
+            Action::Paste => {
+                self.paste();
+            }
+            Action::Undo => {
+                self.selection_anchor = None;
+                match self.document_mut().undo() {
+                    Some(position) => self.cursor_position = position,
+                    None => self.status_message = Status_Message::create("Nothing to undo.".to_string()),
+                }
+            }
+            Action::Redo => {
+                self.selection_anchor = None;
+                match self.document_mut().redo() {
+                    Some(position) => self.cursor_position = position,
+                    None => self.status_message = Status_Message::create("Nothing to redo.".to_string()),
+                }
+            }
+            Action::ReloadConfig => {
+                self.reload_config();
+            }
+            Action::CommandLine => {
+                self.command_line()?;
+            }
+            Action::GoToLine => {
+                self.go_to_line()?;
+            }
+            Action::ToggleWrap => {
+                let buffer = self.current_buffer;
+                let wrap = self.buffers[buffer].wrap == false;
+                self.buffers[buffer].wrap = wrap;
+                //the offsets of the panes showing the buffer no longer mean the same thing
+                self.offset.x = 0;
+                for pane in self.panes.iter_mut().filter(|pane| pane.buffer == buffer) {
+                    pane.offset.x = 0;
+                }
+                let state = if wrap == true { "on" } else { "off" };
+                self.status_message = Status_Message::create(format!("Wrapping {}.", state));
+            }
+            Action::MoveUp
+            | Action::MoveDown
+            | Action::MoveLeft
+            | Action::MoveRight
+            | Action::PageUp
+            | Action::PageDown
+            | Action::PageLeft
+            | Action::PageRight => self.move_cursor(action),
         }
 
-        self.scroll();
+        return Ok(());
+    }
 
-        if self.quit_times < QUIT_TIMES {
-            self.quit_times = QUIT_TIMES;
-            self.status_message = Status_Message::create(String::new());
+    //Reload Config
+    //reads the config file again, so changes to it take effect without restarting the editor
+    fn reload_config(&mut self) {
+        let (config, errors) = Config::load();
+        if config.vim_mode != self.config.vim_mode {
+            self.reset_modal();
+        }
+        self.config = config;
+        self.quit_times = self.config.quit_times;
+        self.terminal.set_alternate_screen(self.config.alternate_screen);
+        self.terminal.set_mouse(self.config.mouse);
+
+        let message = match errors.first() {
+            None => "Config reloaded.".to_string(),
+            Some(error) => config_error_message(error, errors.len()),
+        };
+        self.status_message = Status_Message::create(message);
+    }
+
+    //turning vim mode on or off starts over in normal mode, finishing the undo group of an insert that was still going on
+    fn reset_modal(&mut self) {
+        if let Some(buffer) = self.modal.open_group.take() {
+            self.buffers[buffer].document.end_undo_group();
+        }
+        self.modal = Modal::default();
+        self.selection_anchor = None;
+    }
+
+    //------------------------------------------------------------------------//
+    //---------------------------- Command Line ------------------------------//
+    //------------------------------------------------------------------------//
+
+    //Command Line
+    //reads a command (":w", ":goto 120", ":set tab_width=8"...) and runs it. Tab completes the names of commands and files, and anything
+    //that goes wrong is shown in the message bar
+    fn command_line(&mut self) -> Result<(), std::io::Error> {
+        let line = match self.prompt_with_completion(":", command::complete)? {
+            Some(line) if line.trim().is_empty() == false => line,
+            _ => return Ok(()),
+        };
+
+        match command::parse(&line) {
+            Ok(command) => self.run_command(command)?,
+            Err(error) => self.status_message = Status_Message::create(format!("ERR: {}", error)),
         }
         return Ok(());
     }
 
-    // f = move cursor to next line (as in forward a line)
-    // d = move cursor to previous line (as in downward a line)
-    //
-    // j = move cursor to previous character 
-    // k = move cursor to next character
-    //
-    // l = move cursor to end of line (as in end of Line)
-    // h = move cursor to begining of line (as in Head of line)
-    //
-    // Enter = move to next page
-    // Backspace = move to previous page
+    //Run Command
+    //":w file" writes a copy of a named buffer to the file, but names the buffer after it if it did not have a name yet (which is what 
+    //":saveas file" always does). ":q" will not quit while there are unsaved changes, ":q!" quits anyway
+    fn run_command(&mut self, command : ExCommand) -> Result<(), std::io::Error> {
+        match command {
+            ExCommand::Write(None) => {
+                self.save();
+            }
+            ExCommand::Write(Some(file_name)) if self.document().file_name.is_some() => {
+                match self.document().write_to(&file_name) {
+                    Ok(()) => self.status_message = Status_Message::create(format!("Written to {}", file_name)),
+                    Err(error) => self.status_message = Status_Message::create(format!("Error writing file: {}", error)),
+                }
+            }
+            ExCommand::Write(Some(file_name)) | ExCommand::SaveAs(file_name) => {
+                self.document_mut().file_name = Some(file_name);
+                self.save();
+            }
+            ExCommand::Edit(file_name) => {
+                self.open_path(file_name)?;
+            }
+            ExCommand::Quit(force) => {
+                self.quit_command(force);
+            }
+            ExCommand::WriteQuit(force) => {
+                self.save();
+                if self.document().is_dirty() == false {
+                    self.quit_command(force);
+                }
+            }
+            ExCommand::Goto(line, column) => {
+                self.go_to(line, column);
+            }
+            ExCommand::Set(None) => {
+                self.status_message = Status_Message::create(self.config.options());
+            }
+            ExCommand::Set(Some(setting)) => {
+                let vim_mode = self.config.vim_mode;
+                match self.config.set(&setting) {
+                    Ok(value) => self.status_message = Status_Message::create(value),
+                    Err(error) => self.status_message = Status_Message::create(format!("ERR: {}", error)),
+                }
+                if self.config.vim_mode != vim_mode {
+                    self.reset_modal();
+                }
+                self.quit_times = self.config.quit_times;
+                self.terminal.set_alternate_screen(self.config.alternate_screen);
+                self.terminal.set_mouse(self.config.mouse);
+            }
+            ExCommand::Run(action) => {
+                self.run_action(action)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn quit_command(&mut self, force : bool) {
+        let dirty_buffers = self.dirty_buffers();
+        if force == false && dirty_buffers > 0 {
+            self.status_message = Status_Message::create(format!("ERR: {} file(s) have unsaved changes (add ! to quit anyway)", dirty_buffers));
+            return;
+        }
+        self.should_quit = true;
+    }
+
+    // move up / down = move cursor to previous / next line (Alt-D / Alt-F by default)
+    // move left / right = move cursor to previous / next character (Alt-J / Alt-K)
+    // page left / right = move cursor a screen width to the left / right (Alt-H / Alt-L)
+    // page up / down = move cursor to previous / next page (Alt-V / Alt-N)
+
+    fn move_cursor(&mut self, action : Action) {
+        if self.is_wrapped() == true && (action == Action::MoveUp || action == Action::MoveDown) {
+            self.move_screen_line(action == Action::MoveDown);
+            return;
+        }
 
-    fn move_cursor(&mut self, key : Key) {
         let Position{ mut x, mut y} = self.cursor_position;
 
-        let terminal_height = self.terminal.size().height as usize;
-        let terminal_width = self.terminal.size().width as usize;
+        let terminal_height = self.text_area().height;
+        let terminal_width = self.text_area().width;
 
-        let height : usize = self.document.len();
-        let width = if let Some(row) = self.document.row(y) {
-            row.get_len()
-        } else {
-            0
-        };
+        let height : usize = self.document().len();
         
-        match key {
-            Key::Alt('f') => {
-                if y < height {
-                    y = y.saturating_add(1);
-                }
-            } 
-            Key::Alt('d') => y = y.saturating_sub(1),
-            Key::Alt('k') => {
-                if x < width {
+        match action {
+            Action::MoveDown if y < height => y = y.saturating_add(1),
+            Action::MoveUp => y = y.saturating_sub(1),
+            //only the graphemes up to the cursor are walked through to find out whether it is at the end of the row
+            Action::MoveRight => {
+                if self.document().has_grapheme(y, x) {
                     x = x + 1;
                 } else if y < height {
                     y = y + 1;
                     x = 0;
                 }
             }
-            Key::Alt('j') => {
+            Action::MoveLeft => {
                 if x > 0 {
                     x = x - 1;
                 } else if y > 0 {
                     y = y - 1;
-                    if let Some(row) = self.document.row(y) {
-                        x = row.get_len();
-                    } else {
-                        x = 0;
-                    }
+                    x = self.document().row_len(y);
                 }
             }
-            Key::Alt('l') => {
+            Action::PageRight => {
+                let width = self.document().row_len(y);
                 x = if x.saturating_add(terminal_width) < width {
-                    x + terminal_width as usize
+                    x + terminal_width
                 } else {
                     width
                 };
             }
-            Key::Alt('h') => {
-                x = if x > terminal_width {
-                    x - terminal_width
-                } else {
-                    0
-                };
+            Action::PageLeft => {
+                x = x.saturating_sub(terminal_width);
             }
-            Key::Alt('n') => {
+            Action::PageDown => {
                 y = if y.saturating_add(terminal_height) < height {
-                    y + terminal_height as usize
+                    y + terminal_height
                 } else {
                     height
                 };
             }
-            Key::Alt('v') => {
-                y = if y > terminal_height {
-                    y - terminal_height
-                } else {
-                    0
-                };
+            Action::PageUp => {
+                y = y.saturating_sub(terminal_height);
             }
             _ => (),
         }
@@ -410,39 +2110,84 @@ impl Editor {
         return self.cursor_position = Position{x, y};
     }
 
-    fn prompt(&mut self, prompt : &str) -> Result<Option<String>, std::io::Error> {
+    //Prompt
+    //this function takes over the message bar to ask the user for some input (a file name, a search query...). The callback is called
+    //after every key press with the key and the current input, which lets features such as search react while the user is still typing
+    fn prompt<C>(&mut self, prompt : &str, callback : C) -> Result<Option<String>, std::io::Error> 
+    where 
+        C : FnMut(&mut Self, Key, &String),
+    {
+        return self.read_input(prompt, None, callback);
+    }
+
+    //Prompt With Completion
+    //a prompt where Tab completes the input. The first Tab replaces it with the first of its completions and each further Tab moves
+    //on to the next one... a single completion is completed again, so Tab can keep going into a directory
+    fn prompt_with_completion(&mut self, prompt : &str, complete : fn(&str) -> Vec<String>) -> Result<Option<String>, std::io::Error> {
+        return self.read_input(prompt, Some(complete), |_, _, _| {});
+    }
+
+    fn read_input<C>(&mut self, prompt : &str, complete : Option<fn(&str) -> Vec<String>>, mut callback : C) -> Result<Option<String>, std::io::Error> 
+    where 
+        C : FnMut(&mut Self, Key, &String),
+    {
         let mut result = String::new();
+        let mut cancelled = false;
+        let mut completions : Vec<String> = Vec::new();
+        let mut completion = 0;
 
         loop {
             self.status_message = Status_Message::create(format!("{}{}", prompt, result));
             self.refresh_screen()?;
 
-            match Terminal::read_key()? {
+            let key = match self.terminal.read_event()? {
+                Event::Key(key) => key,
+                Event::Mouse(_) => continue,
+                Event::Paste(text) => {
+                    result.extend(text.chars().filter(|c| c.is_control() == false));
+                    continue;
+                }
+                Event::Resize => {
+                    self.scroll();
+                    continue;
+                }
+                //a prompt can be left open for a long time, the changes made before it still need to reach the swap file
+                Event::Tick => {
+                    self.update_swap_file();
+                    continue;
+                }
+            };
+            match key {
                 Key::Backspace => {
-                    if !result.is_empty() {
-                        result.truncate(result.len() - 1);
-                    }
+                    result.pop();
                 }
                 Key::Char('\n') => {
                     break;
                 }
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
+                Key::Char('\t') if complete.is_some() => {
+                    if completions.len() > 1 && completions.get(completion) == Some(&result) {
+                        completion = (completion + 1) % completions.len();
+                    } else {
+                        completions = complete.map(|complete| complete(&result)).unwrap_or_default();
+                        completion = 0;
+                    }
+                    if let Some(completed) = completions.get(completion) {
+                        result = completed.clone();
                     }
                 }
+                Key::Char(c) if !c.is_control() => result.push(c),
                 Key::Esc => {
-                    result.truncate(0);
+                    cancelled = true;
                     break;
                 }
-                _ => {
-                    ();
-                }
+                _ => (),
             }
+
+            callback(self, key, &result);
         }
 
         self.status_message = Status_Message::create(String::new());
-        if result.is_empty() {
+        if cancelled == true {
             return Ok(None);
         }
 
@@ -452,12 +2197,70 @@ impl Editor {
     //------------------------------------------------------------------------//
     //------------------------------ Kill Editor -----------------------------//
     //------------------------------------------------------------------------//
-    fn die(&self, e : std::io::Error) {
-        Terminal::clear_screen();
-        panic!("{}", e);
+    //stops the editor after an error it cannot carry on from. What has not been saved is kept in swap files first (to be recovered the
+    //next time the files are opened), then the terminal is put back the way it was so the error can be read on a line of its own
+    fn die(&mut self, e : std::io::Error) -> ! {
+        self.write_swap_files();
+        self.terminal.restore();
+        eprintln!("ERR: {}", e);
+        if self.dirty_buffers() > 0 {
+            eprintln!("{}", SWAP_FILES_KEPT);
+        }
+        process::exit(1);
+    }
+
+}
+
+//File Arguments
+//the files named on the command line, with the line (and column) to open each of them at: "+120 file" opens the file at line 120, and
+//"file:120:5" (the way compilers and grep point at a place in a file) at line 120, column 5... unless a file with that whole name exists
+fn file_arguments(args : &[String]) -> Vec<(String, Option<Location>)> {
+    let mut files = Vec::new();
+    let mut location = None;
+
+    for arg in args {
+        if let Some(line) = arg.strip_prefix('+').and_then(|line| line.parse::<usize>().ok()) {
+            location = Some((line, None));
+            continue;
+        }
+
+        let mut file_name = arg.as_str();
+        if location.is_none() && Path::new(arg).exists() == false {
+            //"a:12:5" could be line 5 of "a:12", but line 12, column 5 of "a" is what is meant
+            for (index, _) in arg.rmatch_indices(':').take(2) {
+                if let Some(found) = command::parse_location(&arg[index + 1..]).filter(|_| index > 0) {
+                    file_name = &arg[..index];
+                    location = Some(found);
+                }
+            }
+        }
+        files.push((file_name.to_string(), location.take()));
     }
 
+    return files;
 }
 
+//the message shown for problems in the config file... only the first one fits in the message bar
+fn config_error_message(error : &str, count : usize) -> String {
+    if count > 1 {
+        return format!("Config error: {} (and {} more)", error, count - 1);
+    }
+    return format!("Config error: {}", error);
+}
 
+//pads the text with spaces, or cuts it short, so that it fills exactly `width` cells on the screen
+fn fit_to_width(text : &str, width : usize) -> String {
+    let mut result = String::new();
+    let mut used : usize = 0;
 
+    for grapheme in text.graphemes(true) {
+        let (shown, grapheme_width) = row::display(grapheme, used, 1);
+        if used + grapheme_width > width {
+            break;
+        }
+        result.push_str(&shown);
+        used = used + grapheme_width;
+    }
+    result.push_str(&" ".repeat(width - used));
+    return result;
+}
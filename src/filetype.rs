@@ -0,0 +1,195 @@
+use crate::highlighting;
+use std::path::Path;
+
+//Highlighting Options
+//describes what the syntax highlighter should look for in a given language. line_prefixes are lines that are highlighted as a whole
+//when they start with the prefix (after any indentation), such as markdown headings or toml tables. Strings that can run over several
+//rows are given by their opening and closing delimiters... a backslash escapes the character after it, except in raw strings
+#[derive(Default)]
+pub struct HighlightingOptions {
+    pub numbers             : bool,
+    pub characters          : bool,
+    pub string_quotes       : &'static [&'static str],
+    pub comment             : Option<&'static str>,
+    pub multiline_comment   : Option<(&'static str, &'static str)>,
+    pub multiline_strings   : &'static [(&'static str, &'static str)],
+    pub raw_strings         : &'static [(&'static str, &'static str)],
+    pub line_prefixes       : &'static [(&'static str, highlighting::Type)],
+    pub primary_keywords    : &'static [&'static str],
+    pub secondary_keywords  : &'static [&'static str],
+}
+
+impl HighlightingOptions {
+    //whether there is nothing at all to look for, as with plain text
+    pub fn is_empty(&self) -> bool {
+        return self.numbers == false
+            && self.characters == false
+            && self.string_quotes.is_empty()
+            && self.comment.is_none()
+            && self.multiline_comment.is_none()
+            && self.multiline_strings.is_empty()
+            && self.raw_strings.is_empty()
+            && self.line_prefixes.is_empty()
+            && self.primary_keywords.is_empty()
+            && self.secondary_keywords.is_empty();
+    }
+}
+
+pub struct FileType {
+    name            : String,
+    hl_opts         : HighlightingOptions,
+}
+
+impl Default for FileType {
+    fn default() -> Self {
+        return Self {
+            name    : String::from("text"),
+            hl_opts : HighlightingOptions::default(),
+        };
+    }
+}
+
+impl FileType {
+
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    pub fn highlighting_options(&self) -> &HighlightingOptions {
+        return &self.hl_opts;
+    }
+
+    //From
+    //picks the language from the extension of the file name, falling back to plain text (no highlighting)
+    pub fn from(file_name : &str) -> Self {
+        let extension = Path::new(file_name).extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+        let (name, hl_opts) = match extension.as_str() {
+            "rs"                                => ("rust", rust()),
+            "c" | "h"                           => ("c", c()),
+            "py" | "pyw"                        => ("python", python()),
+            "json"                              => ("json", json()),
+            "toml"                              => ("toml", toml()),
+            "md" | "markdown"                   => ("markdown", markdown()),
+            _                                   => return Self::default(),
+        };
+
+        return Self {
+            name    : name.to_string(),
+            hl_opts,
+        };
+    }
+}
+
+//------------------------------------------------------------------------//
+//------------------------ Language Definitions --------------------------//
+//------------------------------------------------------------------------//
+
+fn rust() -> HighlightingOptions {
+    return HighlightingOptions {
+        numbers             : true,
+        characters          : true,
+        string_quotes       : &[],
+        comment             : Some("//"),
+        multiline_comment   : Some(("/*", "*/")),
+        multiline_strings   : &[("\"", "\"")],
+        raw_strings         : &[("r##\"", "\"##"), ("r#\"", "\"#"), ("r\"", "\"")],
+        line_prefixes       : &[],
+        primary_keywords    : &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for", "if",
+            "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", 
+            "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        secondary_keywords  : &[
+            "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32", "f64", "str",
+            "String", "Vec", "Option", "Result", "Box", "Some", "None", "Ok", "Err",
+        ],
+    };
+}
+
+fn c() -> HighlightingOptions {
+    return HighlightingOptions {
+        numbers             : true,
+        characters          : true,
+        string_quotes       : &["\""],
+        comment             : Some("//"),
+        multiline_comment   : Some(("/*", "*/")),
+        multiline_strings   : &[],
+        raw_strings         : &[],
+        line_prefixes       : &[("#", highlighting::Type::SecondaryKeyword)],
+        primary_keywords    : &[
+            "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern", "for", "goto", "if", "inline",
+            "register", "restrict", "return", "sizeof", "static", "struct", "switch", "typedef", "union", "volatile", "while", "NULL",
+        ],
+        secondary_keywords  : &[
+            "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void", "bool", "size_t", "int8_t", "int16_t",
+            "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+        ],
+    };
+}
+
+fn python() -> HighlightingOptions {
+    return HighlightingOptions {
+        numbers             : true,
+        characters          : false,
+        string_quotes       : &["\"", "'"],
+        comment             : Some("#"),
+        multiline_comment   : None,
+        multiline_strings   : &[("\"\"\"", "\"\"\""), ("'''", "'''")],
+        raw_strings         : &[],
+        line_prefixes       : &[("@", highlighting::Type::SecondaryKeyword)],
+        primary_keywords    : &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "False",
+            "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise",
+            "return", "True", "try", "while", "with", "yield",
+        ],
+        secondary_keywords  : &[
+            "bool", "bytes", "dict", "float", "int", "list", "object", "print", "range", "self", "set", "str", "super", "tuple", "len",
+        ],
+    };
+}
+
+fn json() -> HighlightingOptions {
+    return HighlightingOptions {
+        numbers             : true,
+        characters          : false,
+        string_quotes       : &["\""],
+        comment             : None,
+        multiline_comment   : None,
+        multiline_strings   : &[],
+        raw_strings         : &[],
+        line_prefixes       : &[],
+        primary_keywords    : &["true", "false", "null"],
+        secondary_keywords  : &[],
+    };
+}
+
+fn toml() -> HighlightingOptions {
+    return HighlightingOptions {
+        numbers             : true,
+        characters          : false,
+        string_quotes       : &["\"", "'"],
+        comment             : Some("#"),
+        multiline_comment   : None,
+        multiline_strings   : &[("\"\"\"", "\"\"\""), ("'''", "'''")],
+        raw_strings         : &[],
+        line_prefixes       : &[("[", highlighting::Type::PrimaryKeyword)],
+        primary_keywords    : &["true", "false"],
+        secondary_keywords  : &[],
+    };
+}
+
+fn markdown() -> HighlightingOptions {
+    return HighlightingOptions {
+        numbers             : false,
+        characters          : false,
+        string_quotes       : &["`"],
+        comment             : None,
+        multiline_comment   : Some(("<!--", "-->")),
+        multiline_strings   : &[("```", "```")],
+        raw_strings         : &[],
+        line_prefixes       : &[("#", highlighting::Type::PrimaryKeyword), (">", highlighting::Type::Comment)],
+        primary_keywords    : &[],
+        secondary_keywords  : &[],
+    };
+}
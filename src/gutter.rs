@@ -0,0 +1,70 @@
+use std::cmp;
+
+//the fewest digits line numbers are given room for, so the text does not move sideways while a new file grows past 9 or 99 rows
+const MIN_DIGITS : usize = 3;
+
+//how line numbers are shown: not at all, as the number of each row, or as the distance of each row from the cursor's row (which
+//shows its own number, so it is still clear where the cursor is)
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+}
+
+impl LineNumbers {
+
+    pub fn from_name(name : &str) -> Option<Self> {
+        return match name {
+            "off"       => Some(LineNumbers::Off),
+            "absolute"  => Some(LineNumbers::Absolute),
+            "relative"  => Some(LineNumbers::Relative),
+            _           => None,
+        };
+    }
+
+    pub fn name(self) -> &'static str {
+        return match self {
+            LineNumbers::Off        => "off",
+            LineNumbers::Absolute   => "absolute",
+            LineNumbers::Relative   => "relative",
+        };
+    }
+}
+
+//Gutter
+//the columns to the left of a pane's text. Its first column is kept for markers about the row (nothing uses it yet, but diagnostics or
+//version control changes can go there without moving the text again), followed by the line number and a space between it and the text.
+//With line numbers off there is no gutter at all
+pub struct Gutter {
+    line_numbers    : LineNumbers,
+    digits          : usize,
+}
+
+impl Gutter {
+
+    //the gutter for a document with the given number of rows... it is wide enough for the number of the last row
+    pub fn create(line_numbers : LineNumbers, rows : usize) -> Self {
+        return Self {
+            line_numbers,
+            digits          : cmp::max(rows.to_string().len(), MIN_DIGITS),
+        };
+    }
+
+    pub fn width(&self) -> usize {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        return 1 + self.digits + 1;
+    }
+
+    //the text of the gutter for a row (both indexes start from 0, and the numbers shown from 1 like the status bar's)
+    pub fn render(&self, index : usize, cursor_row : usize) -> String {
+        let number = match self.line_numbers {
+            LineNumbers::Off => return String::new(),
+            LineNumbers::Relative if index != cursor_row => index.abs_diff(cursor_row),
+            _ => index + 1,
+        };
+        return format!(" {:>width$} ", number, width = self.digits);
+    }
+}
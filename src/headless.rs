@@ -0,0 +1,106 @@
+use crate::Position;
+use crate::clipboard::Clipboard;
+use crate::screen::Screen;
+use crate::terminal::{Event, Size, Terminal};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use termion::event::Key;
+
+//HeadlessTerminal
+//a terminal that only exists in memory, for driving the editor from tests. Its events are given to it up front (the way a user would
+//have typed them), and the frame drawn on it stays behind so what the editor showed can be checked afterwards. Once the events run
+//out, reading another one fails with UnexpectedEof... which is also how the editor's run comes to an end
+pub struct HeadlessTerminal {
+    size                : Size,
+    screen              : RefCell<Screen>,
+    events              : VecDeque<Event>,
+    frames              : Cell<usize>,
+    alternate_screen    : bool,
+}
+
+impl HeadlessTerminal {
+
+    //a screen of the given size (the last line of which is the message bar, like on a real terminal)
+    pub fn create(width : u16, height : u16, events : Vec<Event>) -> Self {
+        return Self {
+            size                : Size {
+                width,
+                height  : height.saturating_sub(1),
+            },
+            screen              : RefCell::new(Screen::create(width as usize, height as usize)),
+            events              : events.into(),
+            frames              : Cell::new(0),
+            alternate_screen    : false,
+        };
+    }
+
+    //------------------------------------------------------------------------//
+    //--------------------------- Struct Getters -----------------------------//
+    //------------------------------------------------------------------------//
+    pub fn frame(&self) -> Ref<'_, Screen> {
+        return self.screen.borrow();
+    }
+
+    //the text on a line of the last frame
+    pub fn line(&self, y : usize) -> String {
+        return self.screen.borrow().line(y);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        return self.screen.borrow().lines();
+    }
+
+    pub fn cursor(&self) -> Position {
+        return self.screen.borrow().cursor();
+    }
+
+    //how many frames have been flushed
+    pub fn frames(&self) -> usize {
+        return self.frames.get();
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        return self.alternate_screen;
+    }
+
+    //the events that have not been read yet
+    pub fn pending(&self) -> usize {
+        return self.events.len();
+    }
+}
+
+impl Terminal for HeadlessTerminal {
+
+    fn size(&self) -> &Size {
+        return &self.size;
+    }
+
+    fn screen(&self) -> RefMut<'_, Screen> {
+        return self.screen.borrow_mut();
+    }
+
+    fn flush(&self) -> Result<(), std::io::Error> {
+        self.frames.set(self.frames.get() + 1);
+        return Ok(());
+    }
+
+    fn set_alternate_screen(&mut self, alternate_screen : bool) {
+        self.alternate_screen = alternate_screen;
+    }
+
+    //copying stays inside the editor, rather than reaching the clipboard of whoever is running the tests
+    fn clipboard(&self) -> Clipboard {
+        return Clipboard::internal();
+    }
+
+    fn read_event(&mut self) -> Result<Event, std::io::Error> {
+        return self.events.pop_front().ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "no more events"));
+    }
+}
+
+//Typed
+//the key presses for typing the text, one character at a time ('\n' being Enter)
+pub fn typed(text : &str) -> Vec<Event> {
+    return text.chars().map(|c| Event::Key(Key::Char(c))).collect();
+}
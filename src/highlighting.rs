@@ -0,0 +1,42 @@
+use termion::color;
+
+//the kind of token each grapheme of a row belongs to, which decides the colour it is drawn in
+#[derive(Clone, Copy, PartialEq)]
+pub enum Type {
+    None,
+    Number,
+    String,
+    Character,
+    Comment,
+    MultilineComment,
+    PrimaryKeyword,
+    SecondaryKeyword,
+}
+
+impl Type {
+    //None is drawn in the terminal's own colour, so it has no colour here
+    pub fn to_color(self) -> Option<color::Rgb> {
+        return match self {
+            Type::None                  => None,
+            Type::Number                => Some(color::Rgb(220, 163, 163)),
+            Type::String                => Some(color::Rgb(211, 54, 130)),
+            Type::Character             => Some(color::Rgb(108, 113, 196)),
+            Type::Comment               => Some(color::Rgb(133, 153, 0)),
+            Type::MultilineComment      => Some(color::Rgb(133, 153, 0)),
+            Type::PrimaryKeyword        => Some(color::Rgb(181, 137, 0)),
+            Type::SecondaryKeyword      => Some(color::Rgb(42, 161, 152)),
+        };
+    }
+}
+
+//State
+//what a row ends inside of, which is where the highlighting of the next row has to start: nothing, a multi-line comment, or a
+//multi-line or raw string (along with the delimiter that closes it)
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum State {
+    #[default]
+    Normal,
+    Comment,
+    String(&'static str),
+    RawString(&'static str),
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use termion::event::Key;
+
+//Action
+//every command that can be bound to a key. Typing text and the keys used inside prompts (search, replace, recovery...) are not actions
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    Save,
+    OpenFile,
+    NextBuffer,
+    PreviousBuffer,
+    WindowCommand,
+    Find,
+    Replace,
+    ToggleLineEnding,
+    Undo,
+    Redo,
+    SetMark,
+    ClearSelection,
+    Copy,
+    Cut,
+    Paste,
+    DeleteBackward,
+    DeleteForward,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    PageUp,
+    PageDown,
+    PageLeft,
+    PageRight,
+    ReloadConfig,
+    CommandLine,
+    GoToLine,
+    ToggleWrap,
+}
+
+//the names actions go by in the config file (and on the command line)
+pub const ACTION_NAMES : &[(Action, &str)] = &[
+    (Action::Quit,              "quit"),
+    (Action::Save,              "save"),
+    (Action::OpenFile,          "open_file"),
+    (Action::NextBuffer,        "next_buffer"),
+    (Action::PreviousBuffer,    "previous_buffer"),
+    (Action::WindowCommand,     "window_command"),
+    (Action::Find,              "find"),
+    (Action::Replace,           "replace"),
+    (Action::ToggleLineEnding,  "toggle_line_ending"),
+    (Action::Undo,              "undo"),
+    (Action::Redo,              "redo"),
+    (Action::SetMark,           "set_mark"),
+    (Action::ClearSelection,    "clear_selection"),
+    (Action::Copy,              "copy"),
+    (Action::Cut,               "cut"),
+    (Action::Paste,             "paste"),
+    (Action::DeleteBackward,    "delete_backward"),
+    (Action::DeleteForward,     "delete_forward"),
+    (Action::MoveUp,            "move_up"),
+    (Action::MoveDown,          "move_down"),
+    (Action::MoveLeft,          "move_left"),
+    (Action::MoveRight,         "move_right"),
+    (Action::PageUp,            "page_up"),
+    (Action::PageDown,          "page_down"),
+    (Action::PageLeft,          "page_left"),
+    (Action::PageRight,         "page_right"),
+    (Action::ReloadConfig,      "reload_config"),
+    (Action::CommandLine,       "command_line"),
+    (Action::GoToLine,          "go_to_line"),
+    (Action::ToggleWrap,        "toggle_wrap"),
+];
+
+impl Action {
+
+    pub fn from_name(name : &str) -> Option<Self> {
+        return ACTION_NAMES.iter().find(|(_, action_name)| *action_name == name).map(|(action, _)| *action);
+    }
+
+    //actions that change the text straight away, the way typing does (which vim mode only allows in insert mode)
+    pub fn is_edit(self) -> bool {
+        return matches!(self, Action::Cut | Action::Paste | Action::DeleteBackward | Action::DeleteForward);
+    }
+}
+
+//Keymap
+//which action each key is bound to. The defaults are the editor's original key bindings, and the config file can bind more keys or 
+//take bindings away
+pub struct Keymap {
+    bindings    : HashMap<Key, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = [
+            (Key::Alt('q'),     Action::Quit),
+            (Key::Ctrl('w'),    Action::Save),
+            (Key::Ctrl('o'),    Action::OpenFile),
+            (Key::Ctrl('n'),    Action::NextBuffer),
+            (Key::Ctrl('p'),    Action::PreviousBuffer),
+            (Key::Alt('w'),     Action::WindowCommand),
+            (Key::Ctrl('f'),    Action::Find),
+            (Key::Ctrl('r'),    Action::Replace),
+            (Key::Ctrl('z'),    Action::Undo),
+            (Key::Ctrl('y'),    Action::Redo),
+            (Key::Alt('m'),     Action::SetMark),
+            (Key::Esc,          Action::ClearSelection),
+            (Key::Ctrl('c'),    Action::Copy),
+            (Key::Ctrl('x'),    Action::Cut),
+            (Key::Ctrl('v'),    Action::Paste),
+            (Key::Backspace,    Action::DeleteBackward),
+            (Key::Delete,       Action::DeleteForward),
+            (Key::Alt('d'),     Action::MoveUp),
+            (Key::Alt('f'),     Action::MoveDown),
+            (Key::Alt('j'),     Action::MoveLeft),
+            (Key::Alt('k'),     Action::MoveRight),
+            (Key::Alt('v'),     Action::PageUp),
+            (Key::Alt('n'),     Action::PageDown),
+            (Key::Alt('h'),     Action::PageLeft),
+            (Key::Alt('l'),     Action::PageRight),
+            (Key::Alt('r'),     Action::ReloadConfig),
+            (Key::Alt('x'),     Action::CommandLine),
+            (Key::Ctrl('g'),    Action::GoToLine),
+            (Key::Alt('z'),     Action::ToggleWrap),
+        ];
+
+        return Self {
+            bindings    : bindings.into_iter().collect(),
+        };
+    }
+}
+
+impl Keymap {
+
+    pub fn get(&self, key : Key) -> Option<Action> {
+        return self.bindings.get(&key).copied();
+    }
+
+    //binds the key to the action, or removes its binding if there is no action
+    pub fn bind(&mut self, key : Key, action : Option<Action>) {
+        match action {
+            Some(action) => self.bindings.insert(key, action),
+            None => self.bindings.remove(&key),
+        };
+    }
+
+    //the key bound to the action (the one with the shortest name if there are several, so the result does not change between runs)
+    pub fn key_for(&self, action : Action) -> Option<Key> {
+        return self.bindings.iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| *key)
+            .min_by_key(|key| (key_name(*key).len(), key_name(*key)));
+    }
+
+    //Help
+    //the help line shown when the editor starts, built from the current bindings. Related actions share an entry, and their keys are 
+    //shortened when they use the same modifier ("Ctrl-N/P")
+    pub fn help(&self) -> String {
+        let entries : [(&str, &[Action]); 9] = [
+            ("write",           &[Action::Save]),
+            ("open",            &[Action::OpenFile]),
+            ("next/prev buffer",&[Action::NextBuffer, Action::PreviousBuffer]),
+            ("find",            &[Action::Find]),
+            ("replace",         &[Action::Replace]),
+            ("mark",            &[Action::SetMark]),
+            ("copy/cut/paste",  &[Action::Copy, Action::Cut, Action::Paste]),
+            ("undo",            &[Action::Undo]),
+            ("quit",            &[Action::Quit]),
+        ];
+
+        let mut help : Vec<String> = Vec::new();
+        for (label, actions) in entries {
+            let keys : Option<Vec<String>> = actions.iter().map(|action| self.key_for(*action).map(key_name)).collect();
+            let keys = match keys {
+                Some(keys) => keys,
+                None => continue,
+            };
+
+            let prefix = keys[0].find('-').map(|index| &keys[0][..=index]).unwrap_or_default();
+            let shared = prefix.is_empty() == false && keys.iter().all(|key| key.starts_with(prefix) && key.len() > prefix.len());
+            let keys : Vec<&str> = keys.iter().enumerate()
+                .map(|(index, key)| if shared == true && index > 0 { &key[prefix.len()..] } else { key.as_str() })
+                .collect();
+            help.push(format!("{} = {}", keys.join("/"), label));
+        }
+
+        return format!("HELP: {}", help.join(" | "));
+    }
+}
+
+//Parse Key
+//reads a key written the way the config file expects it: "ctrl-w", "alt-f", "esc", "backspace", "f5", "pageup" or a single character
+pub fn parse_key(text : &str) -> Option<Key> {
+    let lowercase = text.to_lowercase();
+    let single_char = |text : &str| -> Option<char> {
+        let mut chars = text.chars();
+        let c = chars.next()?;
+        return if chars.next().is_none() { Some(c) } else { None };
+    };
+
+    if let Some(rest) = lowercase.strip_prefix("ctrl-") {
+        return single_char(rest).map(Key::Ctrl);
+    }
+    if let Some(rest) = text.get(4..).filter(|_| lowercase.starts_with("alt-")) {
+        return single_char(rest).map(Key::Alt);
+    }
+    if let Some(number) = lowercase.strip_prefix('f').and_then(|number| number.parse::<u8>().ok()) {
+        return Some(Key::F(number));
+    }
+
+    return match lowercase.as_str() {
+        "esc" | "escape"    => Some(Key::Esc),
+        "backspace"         => Some(Key::Backspace),
+        "delete" | "del"    => Some(Key::Delete),
+        "insert"            => Some(Key::Insert),
+        "up"                => Some(Key::Up),
+        "down"              => Some(Key::Down),
+        "left"              => Some(Key::Left),
+        "right"             => Some(Key::Right),
+        "home"              => Some(Key::Home),
+        "end"               => Some(Key::End),
+        "pageup"            => Some(Key::PageUp),
+        "pagedown"          => Some(Key::PageDown),
+        "tab"               => Some(Key::Char('\t')),
+        "enter"             => Some(Key::Char('\n')),
+        _                   => single_char(text).map(Key::Char),
+    };
+}
+
+//the name of a key as shown in the help line
+pub fn key_name(key : Key) -> String {
+    return match key {
+        Key::Ctrl(c)        => format!("Ctrl-{}", c.to_ascii_uppercase()),
+        Key::Alt(c)         => format!("Alt-{}", c.to_ascii_uppercase()),
+        Key::F(number)      => format!("F{}", number),
+        Key::Char('\t')     => "Tab".to_string(),
+        Key::Char('\n')     => "Enter".to_string(),
+        Key::Char(c)        => c.to_string(),
+        Key::Esc            => "Esc".to_string(),
+        Key::Backspace      => "Backspace".to_string(),
+        Key::Delete         => "Delete".to_string(),
+        Key::Insert         => "Insert".to_string(),
+        Key::Up             => "Up".to_string(),
+        Key::Down           => "Down".to_string(),
+        Key::Left           => "Left".to_string(),
+        Key::Right          => "Right".to_string(),
+        Key::Home           => "Home".to_string(),
+        Key::End            => "End".to_string(),
+        Key::PageUp         => "PageUp".to_string(),
+        Key::PageDown       => "PageDown".to_string(),
+        _                   => "?".to_string(),
+    };
+}
@@ -0,0 +1,192 @@
+use std::cmp;
+
+const MIN_RATIO : u16 = 10;
+const MAX_RATIO : u16 = 90;
+
+//Rect
+//an area of the screen, in terminal cells, starting from the top left corner (0, 0)
+#[derive(Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x       : usize,
+    pub y       : usize,
+    pub width   : usize,
+    pub height  : usize,
+}
+
+impl Rect {
+
+    pub fn contains(&self, x : usize, y : usize) -> bool {
+        return self.x <= x && x < self.x + self.width && self.y <= y && y < self.y + self.height;
+    }
+}
+
+//Horizontal splits put the panes one above the other, vertical splits put them side by side (the same way vim names them)
+#[derive(Clone, Copy, PartialEq)]
+pub enum SplitDirection {
+    Horizontal,
+    Vertical,
+}
+
+//Layout
+//the window layout is a tree... each leaf is a pane (by its index in the editor's list of panes) and each split divides its area between
+//two smaller layouts. ratio is the share of the area (in percent) given to the first one
+pub enum Layout {
+    Pane(usize),
+    Split {
+        direction   : SplitDirection,
+        ratio       : u16,
+        first       : Box<Layout>,
+        second      : Box<Layout>,
+    },
+}
+
+impl Layout {
+
+    //Areas
+    //works out the area of the screen that each pane gets, in the order the panes appear on the screen. Vertical splits keep one column
+    //free between the two sides for the separator
+    pub fn areas(&self, area : Rect) -> Vec<(usize, Rect)> {
+        match self {
+            Layout::Pane(pane) => {
+                return vec![(*pane, area)];
+            }
+            Layout::Split { direction, ratio, first, second } => {
+                let (first_area, second_area) = split_area(area, *direction, *ratio);
+                let mut areas = first.areas(first_area);
+                areas.append(&mut second.areas(second_area));
+                return areas;
+            }
+        }
+    }
+
+    //Separators
+    //the one column wide areas drawn between the two sides of each vertical split
+    pub fn separators(&self, area : Rect) -> Vec<Rect> {
+        match self {
+            Layout::Pane(_) => {
+                return Vec::new();
+            }
+            Layout::Split { direction, ratio, first, second } => {
+                let (first_area, second_area) = split_area(area, *direction, *ratio);
+                let mut separators = first.separators(first_area);
+                separators.append(&mut second.separators(second_area));
+                if *direction == SplitDirection::Vertical {
+                    separators.push(Rect { x: first_area.x + first_area.width, y: area.y, width: 1, height: area.height });
+                }
+                return separators;
+            }
+        }
+    }
+
+    //Split
+    //replaces the given pane with a split showing it and the new pane
+    pub fn split(&mut self, pane : usize, new_pane : usize, direction : SplitDirection) {
+        match self {
+            Layout::Pane(index) if *index == pane => {
+                *self = Layout::Split {
+                    direction,
+                    ratio   : 50,
+                    first   : Box::new(Layout::Pane(pane)),
+                    second  : Box::new(Layout::Pane(new_pane)),
+                };
+            }
+            Layout::Pane(_) => (),
+            Layout::Split { first, second, .. } => {
+                first.split(pane, new_pane, direction);
+                second.split(pane, new_pane, direction);
+            }
+        }
+    }
+
+    //Remove
+    //takes the pane out of the layout, giving its space to whatever it was split with. Panes after it are renumbered so the layout still
+    //matches the editor's list of panes once the pane has been removed from it
+    pub fn remove(&mut self, pane : usize) {
+        self.detach(pane);
+        self.renumber(pane);
+    }
+
+    fn detach(&mut self, pane : usize) {
+        let mut remaining = None;
+
+        if let Layout::Split { first, second, .. } = self {
+            if matches!(**first, Layout::Pane(index) if index == pane) {
+                remaining = Some(std::mem::replace(&mut **second, Layout::Pane(0)));
+            } else if matches!(**second, Layout::Pane(index) if index == pane) {
+                remaining = Some(std::mem::replace(&mut **first, Layout::Pane(0)));
+            } else {
+                first.detach(pane);
+                second.detach(pane);
+            }
+        }
+
+        if let Some(remaining) = remaining {
+            *self = remaining;
+        }
+    }
+
+    fn renumber(&mut self, removed : usize) {
+        match self {
+            Layout::Pane(index) => {
+                if *index > removed {
+                    *index = *index - 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    //Resize
+    //grows (or shrinks, for a negative amount) the pane by moving the divider of the closest split that contains it. Returns false if
+    //the pane is not part of any split
+    pub fn resize(&mut self, pane : usize, amount : i16) -> bool {
+        if let Layout::Split { ratio, first, second, .. } = self {
+            if first.resize(pane, amount) == true || second.resize(pane, amount) == true {
+                return true;
+            }
+
+            let change = if first.contains(pane) == true {
+                amount
+            } else if second.contains(pane) == true {
+                -amount
+            } else {
+                return false;
+            };
+
+            *ratio = cmp::max(MIN_RATIO as i16, cmp::min(MAX_RATIO as i16, *ratio as i16 + change)) as u16;
+            return true;
+        }
+
+        return false;
+    }
+
+    pub fn contains(&self, pane : usize) -> bool {
+        return match self {
+            Layout::Pane(index) => *index == pane,
+            Layout::Split { first, second, .. } => first.contains(pane) || second.contains(pane),
+        };
+    }
+}
+
+fn split_area(area : Rect, direction : SplitDirection, ratio : u16) -> (Rect, Rect) {
+    match direction {
+        SplitDirection::Horizontal => {
+            let first_height = cmp::min(cmp::max(area.height * ratio as usize / 100, 1), area.height.saturating_sub(1));
+            return (
+                Rect { height: first_height, ..area },
+                Rect { y: area.y + first_height, height: area.height - first_height, ..area },
+            );
+        }
+        SplitDirection::Vertical => {
+            let first_width = cmp::min(cmp::max(area.width.saturating_sub(1) * ratio as usize / 100, 1), area.width.saturating_sub(2));
+            let second_width = area.width.saturating_sub(first_width + 1);
+            return (
+                Rect { width: first_width, ..area },
+                Rect { x: area.x + first_width + 1, width: second_width, ..area },
+            );
+        }
+    }
+}
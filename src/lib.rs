@@ -0,0 +1,28 @@
+mod row;
+mod clipboard;
+mod command;
+mod config;
+mod document;
+mod editor;
+mod filetype;
+mod gutter;
+mod headless;
+mod highlighting;
+mod keymap;
+mod layout;
+mod modal;
+mod screen;
+mod swap;
+mod terminal;
+
+pub use clipboard::Clipboard;
+pub use config::Config;
+pub use document::Document;
+pub use document::LineEnding;
+pub use editor::Editor;
+pub use editor::Position;
+pub use editor::SearchDirection;
+pub use headless::{typed, HeadlessTerminal};
+pub use row::Row;
+pub use screen::Screen;
+pub use terminal::{Event, Size, Terminal, TermionTerminal};
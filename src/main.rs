@@ -1,13 +1,4 @@
-mod row;
-mod document;
-mod editor;
-mod terminal;
-
-pub use document::Document;
-use editor::Editor;
-pub use editor::Position;
-pub use row::Row;
-pub use terminal::Terminal;
+use text_editor::Editor;
 
 
 // fn cntrl_character(c : char) -> u8 {
@@ -21,5 +12,3 @@ fn main() {
     editor.run();
     //Editor::default().run();
 }
-
-
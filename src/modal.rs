@@ -0,0 +1,472 @@
+use crate::Document;
+use crate::Position;
+use termion::event::Key;
+
+//the largest count a command can be given... anything typed above it is taken as this, so that adding to or multiplying counts can
+//not overflow
+pub const MAX_COUNT : usize = 100_000;
+
+//Mode
+//with vim mode turned on, the editor starts in normal mode, where letters are commands rather than text. Insert mode types text the
+//usual way, and visual mode selects text from where it was entered to the cursor
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        return match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+
+impl Motion {
+
+    //motions that work on whole rows when they are used with an operator ("dj" deletes two rows)
+    pub fn is_linewise(self) -> bool {
+        return matches!(self, Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine);
+    }
+
+    //motions that include the grapheme they land on when they are used with an operator ("de" deletes the last letter of the word, "d$"
+    //the last letter of the row)
+    pub fn is_inclusive(self) -> bool {
+        return self == Motion::WordEnd || self == Motion::LineEnd;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+//where insert mode starts: i = at the cursor, a = after it, I = at the first non-blank of the row, A = at the end of the row,
+//o / O = on a new row below / above
+#[derive(Clone, Copy, PartialEq)]
+pub enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    NewLineBelow,
+    NewLineAbove,
+}
+
+//Command
+//what a sequence of keys typed in normal (or visual) mode asks for. Counts are None when no count was typed, as some commands treat
+//that differently from a count of 1 (G goes to the last row, 3G goes to row 3)
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command {
+    Move(Motion, Option<usize>),
+    Operate(Operator, Motion, Option<usize>),
+    OperateLines(Operator, usize),
+    OperateSelection(Operator),
+    DeleteChar(usize),
+    Insert(InsertAt),
+    Paste(bool, usize),
+    Undo(usize),
+    Repeat(usize),
+    ToggleVisual,
+    OpenCommandLine,
+}
+
+impl Command {
+
+    //commands that change the text, which "." repeats
+    pub fn is_change(self) -> bool {
+        return match self {
+            Command::Operate(operator, _, _) | Command::OperateLines(operator, _) => operator != Operator::Yank,
+            Command::DeleteChar(_) | Command::Insert(_) | Command::Paste(_, _) => true,
+            _ => false,
+        };
+    }
+}
+
+pub enum Parse {
+    Incomplete,
+    Invalid,
+    Complete(Command),
+}
+
+//Modal
+//the state of the vim layer: the current mode, the keys of the command being typed, and the keys of the last change (recorded as it
+//happens, including the text typed in insert mode) so that "." can play them back. register is the unnamed register, holding the text
+//last yanked or deleted for p and P (only yanking puts it on the system clipboard as well), linewise says whether that text was made of
+//whole rows, and open_group is the buffer whose undo group is open while in insert mode
+pub struct Modal {
+    pub mode        : Mode,
+    pending         : String,
+    recording       : Vec<Key>,
+    last_change     : Vec<Key>,
+    pub register    : String,
+    pub linewise    : bool,
+    pub open_group  : Option<usize>,
+}
+
+impl Default for Modal {
+    fn default() -> Self {
+        return Self {
+            mode        : Mode::Normal,
+            pending     : String::new(),
+            recording   : Vec::new(),
+            last_change : Vec::new(),
+            register    : String::new(),
+            linewise    : false,
+            open_group  : None,
+        };
+    }
+}
+
+impl Modal {
+
+    //adds a typed character to the pending command, returning the command once it is complete. Invalid commands are thrown away
+    pub fn push(&mut self, c : char) -> Option<Command> {
+        self.pending.push(c);
+
+        match parse(&self.pending, self.mode == Mode::Visual) {
+            Parse::Incomplete => return None,
+            Parse::Invalid => {
+                self.pending.clear();
+                return None;
+            }
+            Parse::Complete(command) => {
+                self.recording = self.pending.chars().map(Key::Char).collect();
+                self.pending.clear();
+                return Some(command);
+            }
+        }
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    //keys typed in insert mode are part of the change that entered it
+    pub fn record(&mut self, key : Key) {
+        self.recording.push(key);
+    }
+
+    //the change that was just recorded becomes the one "." repeats
+    pub fn finish_change(&mut self) {
+        self.last_change = std::mem::take(&mut self.recording);
+    }
+
+    pub fn last_change(&self) -> Vec<Key> {
+        return self.last_change.clone();
+    }
+}
+
+//Parse
+//a command is an optional count followed by either a motion, an operator with a motion (which can have its own count: "2d3w"
+//deletes six words), a doubled operator for whole rows ("dd"), or one of the single letter commands
+pub fn parse(keys : &str, visual : bool) -> Parse {
+    let (count, rest) = split_count(keys);
+    let c = match rest.chars().next() {
+        Some(c) => c,
+        None => return Parse::Incomplete,
+    };
+    let after = &rest[c.len_utf8()..];
+    let repeat = count.unwrap_or(1);
+
+    let operator = match c {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    };
+
+    if let Some(operator) = operator {
+        if visual == true {
+            return Parse::Complete(Command::OperateSelection(operator));
+        }
+
+        let (motion_count, motion_keys) = split_count(after);
+        if motion_keys.is_empty() {
+            return Parse::Incomplete;
+        }
+        let count = match (count, motion_count) {
+            (None, None) => None,
+            _ => Some(std::cmp::min(repeat.saturating_mul(motion_count.unwrap_or(1)), MAX_COUNT)),
+        };
+        if motion_keys.starts_with(c) && motion_keys.len() == 1 {
+            return Parse::Complete(Command::OperateLines(operator, count.unwrap_or(1)));
+        }
+        return match parse_motion(motion_keys) {
+            Parse::Complete(Command::Move(motion, _)) => Parse::Complete(Command::Operate(operator, motion, count)),
+            other => other,
+        };
+    }
+
+    let motion = || match parse_motion(rest) {
+        Parse::Complete(Command::Move(motion, _)) => Parse::Complete(Command::Move(motion, count)),
+        other => other,
+    };
+    if after.is_empty() == false {
+        return motion();
+    }
+
+    return match c {
+        'x' if visual == true => Parse::Complete(Command::OperateSelection(Operator::Delete)),
+        'x' => Parse::Complete(Command::DeleteChar(repeat)),
+        'i' => Parse::Complete(Command::Insert(InsertAt::Cursor)),
+        'a' => Parse::Complete(Command::Insert(InsertAt::After)),
+        'I' => Parse::Complete(Command::Insert(InsertAt::LineStart)),
+        'A' => Parse::Complete(Command::Insert(InsertAt::LineEnd)),
+        'o' => Parse::Complete(Command::Insert(InsertAt::NewLineBelow)),
+        'O' => Parse::Complete(Command::Insert(InsertAt::NewLineAbove)),
+        'p' => Parse::Complete(Command::Paste(false, repeat)),
+        'P' => Parse::Complete(Command::Paste(true, repeat)),
+        'u' => Parse::Complete(Command::Undo(repeat)),
+        '.' => Parse::Complete(Command::Repeat(repeat)),
+        'v' => Parse::Complete(Command::ToggleVisual),
+        ':' => Parse::Complete(Command::OpenCommandLine),
+        _ => motion(),
+    };
+}
+
+fn parse_motion(keys : &str) -> Parse {
+    let motion = match keys {
+        "h"     => Motion::Left,
+        "l"     => Motion::Right,
+        "k"     => Motion::Up,
+        "j"     => Motion::Down,
+        "w"     => Motion::WordForward,
+        "b"     => Motion::WordBackward,
+        "e"     => Motion::WordEnd,
+        "0"     => Motion::LineStart,
+        "$"     => Motion::LineEnd,
+        "gg"    => Motion::FirstLine,
+        "G"     => Motion::LastLine,
+        "g"     => return Parse::Incomplete,
+        _       => return Parse::Invalid,
+    };
+
+    return Parse::Complete(Command::Move(motion, None));
+}
+
+//splits the count off the front of the keys... a 0 on its own is the motion to the start of the row, not a count
+fn split_count(keys : &str) -> (Option<usize>, &str) {
+    if keys.starts_with('0') {
+        return (None, keys);
+    }
+
+    let digits = keys.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return (None, keys);
+    }
+    let count = keys[..digits].parse().unwrap_or(MAX_COUNT);
+    return (Some(std::cmp::min(count, MAX_COUNT)), &keys[digits..]);
+}
+
+//------------------------------------------------------------------------//
+//------------------------------ Motions ---------------------------------//
+//------------------------------------------------------------------------//
+
+//Target
+//where the motion takes the cursor, repeated `count` times. The row motions (gg, G) land on the first non-blank grapheme of the row.
+//h, j, k and l are left where they are, as the editor moves them with its own cursor movement
+pub fn target(document : &Document, at : Position, motion : Motion, count : Option<usize>) -> Position {
+    let repeat = count.unwrap_or(1);
+    let last_row = document.len().saturating_sub(1);
+
+    return match motion {
+        Motion::Left | Motion::Right | Motion::Up | Motion::Down => at,
+        Motion::LineStart => Position { x: 0, y: at.y },
+        Motion::LineEnd => Position { x: document.row_len(at.y).saturating_sub(1), y: at.y },
+        Motion::FirstLine => {
+            let y = std::cmp::min(count.unwrap_or(1).saturating_sub(1), last_row);
+            Position { x: first_non_blank(document, y), y }
+        }
+        Motion::LastLine => {
+            let y = std::cmp::min(count.map(|line| line.saturating_sub(1)).unwrap_or(last_row), last_row);
+            Position { x: first_non_blank(document, y), y }
+        }
+        Motion::WordForward | Motion::WordBackward | Motion::WordEnd => {
+            let mut scanner = Scanner::create(document, at);
+            for _ in 0..repeat {
+                match motion {
+                    Motion::WordForward => scanner.word_forward(),
+                    Motion::WordBackward => scanner.word_backward(),
+                    _ => scanner.word_end(),
+                }
+            }
+            scanner.position
+        }
+    };
+}
+
+pub fn first_non_blank(document : &Document, y : usize) -> usize {
+    return document.graphemes(y).position(|grapheme| grapheme.trim().is_empty() == false).unwrap_or(0);
+}
+
+//the position one grapheme further on, moving onto the next row after the end of a row
+pub fn next_position(document : &Document, at : Position) -> Position {
+    let mut scanner = Scanner::create(document, at);
+    scanner.forward();
+    return scanner.position;
+}
+
+//the end of the word the cursor is on, just after its last grapheme ("cw" changes up to here rather than up to the next word)
+pub fn end_of_word(document : &Document, at : Position) -> Position {
+    let mut scanner = Scanner::create(document, at);
+    let class = scanner.class();
+    while scanner.class() == class && scanner.position.y == at.y {
+        if scanner.forward() == false {
+            break;
+        }
+    }
+    return scanner.position;
+}
+
+pub fn is_blank_at(document : &Document, at : Position) -> bool {
+    return Scanner::create(document, at).class() == Class::Blank;
+}
+
+//graphemes are grouped into words the way vim does it: runs of letters, digits and underscores, runs of other non-blank characters,
+//and blanks (the end of each row counts as a blank)
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+//Scanner
+//walks through the document one grapheme at a time, keeping the graphemes of the current row so that it does not rebuild the row on
+//every step
+struct Scanner<'a> {
+    document    : &'a Document,
+    position    : Position,
+    graphemes   : Vec<String>,
+}
+
+impl<'a> Scanner<'a> {
+
+    fn create(document : &'a Document, position : Position) -> Self {
+        let mut scanner = Self { document, position, graphemes: Vec::new() };
+        scanner.load_row();
+        return scanner;
+    }
+
+    fn load_row(&mut self) {
+        self.graphemes = self.document.graphemes(self.position.y).map(|grapheme| grapheme.into_owned()).collect();
+    }
+
+    fn class(&self) -> Class {
+        return match self.graphemes.get(self.position.x) {
+            None => Class::Blank,
+            Some(grapheme) if grapheme.trim().is_empty() => Class::Blank,
+            Some(grapheme) if grapheme.chars().all(|c| c.is_alphanumeric() || c == '_') => Class::Word,
+            Some(_) => Class::Punctuation,
+        };
+    }
+
+    //an empty row is a word of its own for the word motions
+    fn is_empty_row(&self) -> bool {
+        return self.graphemes.is_empty();
+    }
+
+    fn forward(&mut self) -> bool {
+        if self.position.x < self.graphemes.len() {
+            self.position.x = self.position.x + 1;
+            return true;
+        }
+        if self.position.y + 1 < self.document.len() {
+            self.position = Position { x: 0, y: self.position.y + 1 };
+            self.load_row();
+            return true;
+        }
+        return false;
+    }
+
+    fn backward(&mut self) -> bool {
+        if self.position.x > 0 {
+            self.position.x = self.position.x - 1;
+            return true;
+        }
+        if self.position.y > 0 {
+            self.position.y = self.position.y - 1;
+            self.load_row();
+            self.position.x = self.graphemes.len();
+            return true;
+        }
+        return false;
+    }
+
+    fn word_forward(&mut self) {
+        let start = self.position.y;
+        let class = self.class();
+        if class != Class::Blank {
+            while self.class() == class {
+                if self.forward() == false {
+                    return;
+                }
+            }
+        }
+        while self.class() == Class::Blank && (self.is_empty_row() == false || self.position.y == start) {
+            if self.forward() == false {
+                return;
+            }
+        }
+    }
+
+    fn word_backward(&mut self) {
+        if self.backward() == false {
+            return;
+        }
+        while self.class() == Class::Blank && self.is_empty_row() == false {
+            if self.backward() == false {
+                return;
+            }
+        }
+        let class = self.class();
+        while self.position.x > 0 {
+            self.position.x = self.position.x - 1;
+            if self.class() != class {
+                self.position.x = self.position.x + 1;
+                return;
+            }
+        }
+    }
+
+    fn word_end(&mut self) {
+        if self.forward() == false {
+            return;
+        }
+        while self.class() == Class::Blank {
+            if self.forward() == false {
+                return;
+            }
+        }
+        let class = self.class();
+        while self.position.x + 1 < self.graphemes.len() {
+            self.position.x = self.position.x + 1;
+            if self.class() != class {
+                self.position.x = self.position.x - 1;
+                return;
+            }
+        }
+    }
+}
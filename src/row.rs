@@ -1,18 +1,25 @@
+use crate::filetype::HighlightingOptions;
+use crate::highlighting;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
 use std::cmp;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Default)]
 pub struct Row {
-    string  : String,
-    len     : usize,
+    string          : String,
+    len             : usize,
+    highlighting    : Vec<highlighting::Type>,
 }
 
 impl Row {
 
     pub fn create(slice : &str) -> Self {
         return Self {
-            string  : String::from(slice),
-            len     : slice.graphemes(true).count(),
+            string          : String::from(slice),
+            len             : slice.graphemes(true).count(),
+            highlighting    : Vec::new(),
         };
     }
 
@@ -21,109 +28,349 @@ impl Row {
     }
 
     pub fn is_empty(&self) -> bool {
-        if self.len == 0 {
-            return true;
-        } else {
-            return false;
-        }
+        return self.len == 0;
     }
 
-    pub fn render(&self, start : usize, end : usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
-        //return self.string.get(start..end).unwrap_or_default().to_string();
-        let mut result = String::new();
-        for grapheme in self.string[..].graphemes(true).skip(start).take(end - start) {
-            //result.push_str(grapheme);
-            if grapheme != "\t" {
-                result.push_str(grapheme);
-            } else {
-                result.push_str("    ");
-            }
+    //Column
+    //the screen column grapheme `at` starts at, counting from the start of the row. Past the end of the row every grapheme counts as one
+    //column, so a cursor that is further to the right than the row is long still has a place
+    pub fn column(&self, at : usize, tab_width : usize) -> usize {
+        let mut column : usize = 0;
+        for grapheme in self.string[..].graphemes(true).take(at) {
+            column = column + display(grapheme, column, tab_width).1;
         }
+        return column + at.saturating_sub(self.len);
+    }
 
-        return result;
+    //how many screen columns the whole row takes up
+    pub fn width(&self, tab_width : usize) -> usize {
+        return self.column(self.len, tab_width);
     }
 
-    pub fn insert(&mut self, at : usize, c : char) {
-        if at >= self.get_len() {
-            self.string.push(c);
-            self.len = self.len + 1;
-            return;
+    //the grapheme that covers the given screen column, or the end of the row if the column is past it
+    pub fn index_at(&self, column : usize, tab_width : usize) -> usize {
+        let mut end : usize = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            end = end + display(grapheme, end, tab_width).1;
+            if end > column {
+                return index;
+            }
         }
+        return self.len;
+    }
 
-        let mut result : String = String::new();
-        let mut length = 0;
+    //Wrap
+    //where the row is broken into lines to fit in `width` columns: the grapheme each line starts with and the column it starts at. A 
+    //line is broken after the last blank that fits on it, so words are only split when one does not fit on a line by itself. When the
+    //last line is exactly full, an empty line follows it so that the end of the row still has a place on the screen
+    pub fn wrap(&self, width : usize, tab_width : usize) -> Vec<(usize, usize)> {
+        let width = cmp::max(width, 1);
+        let mut lines : Vec<(usize, usize)> = vec![(0, 0)];
+        let mut column : usize = 0;
+        let mut break_at : Option<(usize, usize)> = None;
 
         for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            length = length + 1;
-            if index == at {
-                length = length + 1;
-                result.push(c);
+            let grapheme_width = display(grapheme, column, tab_width).1;
+            let (line_start, line_column) = lines[lines.len() - 1];
+            if column + grapheme_width - line_column > width && index > line_start {
+                lines.push(break_at.take().unwrap_or((index, column)));
             }
 
-            result.push_str(grapheme);
+            column = column + grapheme_width;
+            if is_whitespace(grapheme) == true {
+                break_at = Some((index + 1, column));
+            }
         }
 
-        self.len = length;
-        self.string = result;
+        if self.len > 0 && column - lines[lines.len() - 1].1 >= width {
+            lines.push((self.len, column));
+        }
+        return lines;
     }
 
-    pub fn delete(&mut self, at : usize) {
-        if at >= self.get_len() {
-            return;
-        }
+    pub fn take_highlighting(&mut self) -> Vec<highlighting::Type> {
+        return std::mem::take(&mut self.highlighting);
+    }
 
-        let mut result : String = String::new();
-        let mut length = 0;
+    //Find Regex
+    //finds every match of the regular expression in the row, each returned as its grapheme index and grapheme length together with the
+    //captures, so the caller can expand a replacement string from them. Matches that do not start and end on a grapheme boundary are
+    //left out. The regex goes through the row once, and as the matches come in order, so does the walk through the graphemes that turns
+    //their byte offsets into grapheme indices
+    pub fn find_regex<'a>(&'a self, regex : &Regex) -> Vec<(usize, usize, Captures<'a>)> {
+        let mut boundaries = self.string.grapheme_indices(true).map(|(index, _)| index).chain([self.string.len()]).enumerate().peekable();
+        let mut seek = |byte : usize| -> Option<usize> {
+            while let Some(&(grapheme, index)) = boundaries.peek() {
+                if index == byte {
+                    return Some(grapheme);
+                }
+                if index > byte {
+                    return None;
+                }
+                boundaries.next();
+            }
+            return None;
+        };
 
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index != at {
-                length = length + 1;
-                result.push_str(grapheme);
+        let mut found = Vec::new();
+        for captures in regex.captures_iter(&self.string) {
+            let (start, end) = match captures.get(0) {
+                Some(matched) => (matched.start(), matched.end()),
+                None => continue,
+            };
+
+            if let Some(x) = seek(start) {
+                if let Some(end) = seek(end) {
+                    found.push((x, end - x, captures));
+                }
             }
         }
 
-        self.len = length;
-        self.string = result;
+        return found;
     }
 
-    pub fn append(&mut self, new : &Self) {
-        self.string = format!("{}{}", self.string, new.string);
-        self.len = self.len + new.len;
+    pub fn as_bytes(&self) -> &[u8] {
+        return self.string.as_bytes();
     }
 
-    pub fn split(&mut self, at : usize) -> Self {
-        let mut row : String = String::new();
-        let mut length = 0;
+    //Highlight
+    //works out the highlighting type of every grapheme in the row, given what the previous row ended inside of (a multi-line comment or
+    //string), and returns what this row ends inside of so the next row can carry on from there
+    pub fn highlight(&mut self, options : &HighlightingOptions, state : highlighting::State) -> highlighting::State {
+        let graphemes : Vec<&str> = self.string[..].graphemes(true).collect();
+        let mut highlighting : Vec<highlighting::Type> = Vec::with_capacity(graphemes.len());
+        let mut state = state;
+        let mut index : usize = 0;
 
-        let mut splitted_row : String = String::new();
-        let mut splitted_length = 0;
+        //lines such as markdown headings are highlighted as a whole
+        if state == highlighting::State::Normal {
+            let indent = graphemes.iter().take_while(|grapheme| is_whitespace(grapheme)).count();
+            for (prefix, highlighting_type) in options.line_prefixes {
+                if matches_at(&graphemes, indent, prefix).is_some() {
+                    self.highlighting = vec![*highlighting_type; graphemes.len()];
+                    return state;
+                }
+            }
+        }
 
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index < at {
-                length = length + 1;
-                row.push_str(grapheme);
-            } else {
-                splitted_length = splitted_length + 1;
-                splitted_row.push_str(grapheme);
+        while index < graphemes.len() {
+            let previous_is_separator = index == 0 || is_separator(graphemes[index - 1]);
+
+            //carrying on inside a multi-line comment or string until its closing delimiter
+            match state {
+                highlighting::State::Comment => {
+                    let closing = options.multiline_comment.map(|(_, end)| end).unwrap_or_default();
+                    index = highlight_until(&graphemes, index, closing, highlighting::Type::MultilineComment, &mut highlighting, &mut state);
+                    continue;
+                }
+                highlighting::State::String(closing) | highlighting::State::RawString(closing) => {
+                    index = highlight_until(&graphemes, index, closing, highlighting::Type::String, &mut highlighting, &mut state);
+                    continue;
+                }
+                highlighting::State::Normal => (),
+            }
+
+            if let Some(comment) = options.comment {
+                if matches_at(&graphemes, index, comment).is_some() {
+                    highlighting.resize(graphemes.len(), highlighting::Type::Comment);
+                    break;
+                }
+            }
+
+            if let Some((start, _)) = options.multiline_comment {
+                if let Some(len) = matches_at(&graphemes, index, start) {
+                    highlighting.resize(index + len, highlighting::Type::MultilineComment);
+                    index = index + len;
+                    state = highlighting::State::Comment;
+                    continue;
+                }
+            }
+
+            //a string is only closed by the delimiter that goes with the one that opened it, so a """ inside a ''' string does not end
+            //it. Raw strings are looked for first, as r"..." would otherwise be taken for the word r followed by a string
+            let raw_string = options.raw_strings.iter().find_map(|(opening, closing)| Some((*closing, matches_at(&graphemes, index, opening)?)));
+            if let Some((closing, len)) = raw_string {
+                highlighting.resize(index + len, highlighting::Type::String);
+                index = index + len;
+                state = highlighting::State::RawString(closing);
+                continue;
+            }
+
+            let multiline_string = options.multiline_strings.iter().find_map(|(opening, closing)| Some((*closing, matches_at(&graphemes, index, opening)?)));
+            if let Some((closing, len)) = multiline_string {
+                highlighting.resize(index + len, highlighting::Type::String);
+                index = index + len;
+                state = highlighting::State::String(closing);
+                continue;
+            }
+
+            if let Some(quote) = options.string_quotes.iter().find(|quote| graphemes[index] == **quote) {
+                index = highlight_string(&graphemes, index, quote, &mut highlighting);
+                continue;
+            }
+
+            if options.characters == true && graphemes[index] == "'" {
+                if let Some(len) = character_len(&graphemes, index) {
+                    highlighting.resize(index + len, highlighting::Type::Character);
+                    index = index + len;
+                    continue;
+                }
+            }
+
+            if options.numbers == true && previous_is_separator == true && is_digit(graphemes[index]) {
+                let len = graphemes[index..].iter().take_while(|grapheme| is_separator(grapheme) == false || **grapheme == ".").count();
+                highlighting.resize(index + len, highlighting::Type::Number);
+                index = index + len;
+                continue;
+            }
+
+            if previous_is_separator == true {
+                let len = graphemes[index..].iter().take_while(|grapheme| is_separator(grapheme) == false).count();
+                let word : String = graphemes[index..index + len].concat();
+                let keyword_type = if options.primary_keywords.contains(&word.as_str()) {
+                    Some(highlighting::Type::PrimaryKeyword)
+                } else if options.secondary_keywords.contains(&word.as_str()) {
+                    Some(highlighting::Type::SecondaryKeyword)
+                } else {
+                    None
+                };
+
+                if let Some(keyword_type) = keyword_type {
+                    highlighting.resize(index + len, keyword_type);
+                    index = index + len;
+                    continue;
+                }
+
+                if len > 0 {
+                    highlighting.resize(index + len, highlighting::Type::None);
+                    index = index + len;
+                    continue;
+                }
             }
+
+            highlighting.push(highlighting::Type::None);
+            index = index + 1;
         }
 
-        self.string = row;
-        self.len = length;
+        self.highlighting = highlighting;
+        return state;
+    }
 
-        return Self {
-            string  : splitted_row,
-            len     : splitted_length,
-        };
+}
 
+//Display
+//what a grapheme looks like on the screen when it starts at the given column, and how many columns it takes up. Tabs reach to the next
+//tab stop, control characters are shown the way the terminal would echo them ("^A"), and graphemes that would otherwise take up no room
+//at all (a combining mark on its own, a zero width space...) are shown by their code point ("<200b>") so the cursor can land on them
+pub fn display(grapheme : &str, column : usize, tab_width : usize) -> (Cow<'_, str>, usize) {
+    let first = grapheme.chars().next().unwrap_or(' ');
 
+    if grapheme == "\t" {
+        let width = tab_width - column % tab_width;
+        return (Cow::Owned(" ".repeat(width)), width);
+    }
+    if first < ' ' || first == '\x7f' {
+        return (Cow::Owned(format!("^{}", (first as u8 ^ 0x40) as char)), 2);
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
-        return self.string.as_bytes();
+    let width = grapheme.width();
+    if width == 0 || first.is_control() {
+        let code = format!("<{:04x}>", first as u32);
+        let width = code.len();
+        return (Cow::Owned(code), width);
+    }
+    return (Cow::Borrowed(grapheme), width);
+}
+
+//------------------------------------------------------------------------//
+//------------------------- Highlighting Helpers -------------------------//
+//------------------------------------------------------------------------//
+
+//returns how many graphemes the pattern covers if the graphemes starting at `index` spell it out
+fn matches_at(graphemes : &[&str], index : usize, pattern : &str) -> Option<usize> {
+    let mut rest = pattern;
+    let mut len = 0;
+
+    for grapheme in graphemes.iter().skip(index) {
+        if rest.is_empty() {
+            break;
+        }
+        rest = rest.strip_prefix(grapheme)?;
+        len = len + 1;
+    }
+
+    if rest.is_empty() == true && pattern.is_empty() == false {
+        return Some(len);
+    }
+    return None;
+}
+
+//highlights everything up to and including the closing delimiter (or the rest of the row if it is not closed on this row), going
+//back to the normal state once the delimiter is found. A backslash escapes the grapheme after it in any string but a raw one. Returns
+//the index of the grapheme after the closed section
+fn highlight_until(graphemes : &[&str], start : usize, closing : &str, highlighting_type : highlighting::Type, 
+               highlighting : &mut Vec<highlighting::Type>, state : &mut highlighting::State) -> usize {
+    let mut index = start;
+
+    while index < graphemes.len() {
+        if let Some(len) = matches_at(graphemes, index, closing) {
+            highlighting.resize(index + len, highlighting_type);
+            *state = highlighting::State::Normal;
+            return index + len;
+        }
+        if matches!(state, highlighting::State::String(_)) && graphemes[index] == "\\" && index + 1 < graphemes.len() {
+            index = index + 1;
+        }
+        index = index + 1;
+    }
+
+    highlighting.resize(graphemes.len(), highlighting_type);
+    return graphemes.len();
+}
+
+//highlights a string that opens with the quote at `start` up to its closing quote (skipping escaped characters), or to the end of the
+//row if the string is not closed. Returns the index of the grapheme after the string
+fn highlight_string(graphemes : &[&str], start : usize, quote : &str, highlighting : &mut Vec<highlighting::Type>) -> usize {
+    let mut index = start + 1;
+
+    while index < graphemes.len() {
+        if graphemes[index] == "\\" {
+            index = index + 2;
+            continue;
+        }
+        if graphemes[index] == quote {
+            index = index + 1;
+            break;
+        }
+        index = index + 1;
     }
 
+    let end = cmp::min(index, graphemes.len());
+    highlighting.resize(end, highlighting::Type::String);
+    return end;
 }
 
+//character literals are either a single grapheme or an escape sequence between single quotes ('a', '\n', '\x7f', '\u{1F600}'). Anything
+//else (such as a rust lifetime) is not a character literal
+fn character_len(graphemes : &[&str], start : usize) -> Option<usize> {
+    if graphemes.get(start + 1) == Some(&"\\") {
+        let closing = graphemes.iter().skip(start + 2).take(10).position(|grapheme| *grapheme == "'")?;
+        return Some(closing + 3);
+    }
+    if graphemes.get(start + 2) == Some(&"'") && graphemes.get(start + 1).is_some() {
+        return Some(3);
+    }
+    return None;
+}
+
+fn is_whitespace(grapheme : &str) -> bool {
+    return grapheme.chars().all(char::is_whitespace);
+}
+
+fn is_digit(grapheme : &str) -> bool {
+    return grapheme.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(false);
+}
+
+fn is_separator(grapheme : &str) -> bool {
+    return grapheme.chars().all(|c| c.is_whitespace() || (c.is_ascii_punctuation() && c != '_'));
+}
@@ -0,0 +1,229 @@
+use crate::Position;
+use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+//Cell
+//one character cell of the screen: the grapheme shown in it and its colours (None for the terminal's own colours). A wide grapheme
+//covers the cell after it as well, which is left with an empty string
+#[derive(Clone, PartialEq)]
+struct Cell {
+    text    : String,
+    fg      : Option<color::Rgb>,
+    bg      : Option<color::Rgb>,
+}
+
+impl Cell {
+    fn blank() -> Self {
+        return Self {
+            text    : " ".to_string(),
+            fg      : None,
+            bg      : None,
+        };
+    }
+}
+
+//Screen
+//the frame being drawn and the last frame written to the terminal. Drawing only changes cells of the frame (at the position and in the
+//colours that were set last, the same way it would on the terminal itself), and flushing writes the cells that are different from the
+//last frame in a single write. Without a last frame (at the start, or after a resize) the whole screen is written. Every backend draws
+//into one of these, and what it shows can be read back a line at a time
+pub struct Screen {
+    width           : usize,
+    height          : usize,
+    cells           : Vec<Cell>,
+    last_frame      : Vec<Cell>,
+    cursor          : Position,
+    fg              : Option<color::Rgb>,
+    bg              : Option<color::Rgb>,
+    cursor_visible  : bool,
+}
+
+impl Screen {
+
+    pub(crate) fn create(width : usize, height : usize) -> Self {
+        return Self {
+            width,
+            height,
+            cells           : vec![Cell::blank(); width * height],
+            last_frame      : Vec::new(),
+            cursor          : Position::default(),
+            fg              : None,
+            bg              : None,
+            cursor_visible  : true,
+        };
+    }
+
+    //------------------------------------------------------------------------//
+    //--------------------------- Struct Getters -----------------------------//
+    //------------------------------------------------------------------------//
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    //the text shown on a line of the screen, with every cell's grapheme (so it is as many columns wide as the screen)
+    pub fn line(&self, y : usize) -> String {
+        if y >= self.height {
+            return String::new();
+        }
+        return self.cells[y * self.width..(y + 1) * self.width].iter().map(|cell| cell.text.as_str()).collect();
+    }
+
+    //the colour the text of a cell is drawn in, None for the terminal's own colour (and for cells outside the screen)
+    pub fn foreground(&self, x : usize, y : usize) -> Option<color::Rgb> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        return self.cells[y * self.width + x].fg;
+    }
+
+    //every line of the screen, from the top down
+    pub fn lines(&self) -> Vec<String> {
+        return (0..self.height).map(|y| self.line(y)).collect();
+    }
+
+    //where the cursor was left, which is where it is shown once the frame is flushed
+    pub fn cursor(&self) -> Position {
+        return self.cursor;
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        return self.cursor_visible;
+    }
+
+    //------------------------------------------------------------------------//
+    //------------------------------- Drawing --------------------------------//
+    //------------------------------------------------------------------------//
+    pub(crate) fn move_cursor(&mut self, position : &Position) {
+        self.cursor = *position;
+    }
+
+    pub(crate) fn set_cursor_visible(&mut self, visible : bool) {
+        self.cursor_visible = visible;
+    }
+
+    //the colours the next text is drawn in (None for the terminal's own colours)
+    pub(crate) fn set_fg(&mut self, color : Option<color::Rgb>) {
+        self.fg = color;
+    }
+
+    pub(crate) fn set_bg(&mut self, color : Option<color::Rgb>) {
+        self.bg = color;
+    }
+
+    //forgets the last frame, so the next flush writes the whole screen (for when the terminal no longer shows it)
+    pub(crate) fn invalidate(&mut self) {
+        self.last_frame.clear();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for y in 0..self.height {
+            self.clear_line(y);
+        }
+    }
+
+    //blanks the line the cursor is on
+    pub(crate) fn clear_current_line(&mut self) {
+        if self.cursor.y < self.height {
+            self.clear_line(self.cursor.y);
+        }
+    }
+
+    //Put
+    //draws the text from the cursor onwards, moving the cursor past it. Whatever does not fit on the line is cut off, and a wide
+    //grapheme that only half fits is drawn as a space
+    pub(crate) fn put(&mut self, text : &str) {
+        for grapheme in text.graphemes(true) {
+            let width = grapheme.width();
+            let Position { x, y } = self.cursor;
+            if width == 0 || y >= self.height || x >= self.width {
+                self.cursor.x = x + width;
+                continue;
+            }
+
+            if x + width > self.width {
+                self.set(x, y, " ");
+            } else {
+                self.set(x, y, grapheme);
+                for covered in x + 1..x + width {
+                    self.set(covered, y, "");
+                }
+            }
+            self.cursor.x = x + width;
+        }
+    }
+
+    //sets a single cell. Drawing over half of a wide grapheme leaves its other half blank, as the grapheme can no longer be shown
+    fn set(&mut self, x : usize, y : usize, text : &str) {
+        let index = y * self.width + x;
+        if text.is_empty() == false {
+            if self.cells[index].text.is_empty() && x > 0 {
+                self.cells[index - 1].text = " ".to_string();
+            }
+            if self.cells[index].text.width() > 1 && x + 1 < self.width {
+                self.cells[index + 1].text = " ".to_string();
+            }
+        }
+        self.cells[index] = Cell { text: text.to_string(), fg: self.fg, bg: self.bg };
+    }
+
+    fn clear_line(&mut self, y : usize) {
+        for x in 0..self.width {
+            self.cells[y * self.width + x] = Cell::blank();
+        }
+    }
+
+    //Render
+    //the output that turns the last frame into this one: the cells that changed (only moving the terminal's cursor when they are not
+    //right after each other, and only changing colours when they are different from the previous cell written), followed by placing 
+    //the cursor where it was left
+    pub(crate) fn render(&mut self) -> String {
+        let mut output = format!("{}{}{}", termion::cursor::Hide, color::Fg(color::Reset), color::Bg(color::Reset));
+        if self.last_frame.len() != self.cells.len() {
+            output.push_str(termion::clear::All.as_ref());
+            self.last_frame = vec![Cell::blank(); self.cells.len()];
+        }
+
+        let mut position : Option<(usize, usize)> = None;
+        let (mut fg, mut bg) : (Option<color::Rgb>, Option<color::Rgb>) = (None, None);
+        for (index, cell) in self.cells.iter().enumerate() {
+            if *cell == self.last_frame[index] || cell.text.is_empty() {
+                continue;
+            }
+
+            let (x, y) = (index % self.width, index / self.width);
+            if position != Some((x, y)) {
+                output.push_str(&termion::cursor::Goto(x as u16 + 1, y as u16 + 1).to_string());
+            }
+            if cell.fg != fg {
+                match cell.fg {
+                    Some(color) => output.push_str(&color::Fg(color).to_string()),
+                    None => output.push_str(&color::Fg(color::Reset).to_string()),
+                }
+                fg = cell.fg;
+            }
+            if cell.bg != bg {
+                match cell.bg {
+                    Some(color) => output.push_str(&color::Bg(color).to_string()),
+                    None => output.push_str(&color::Bg(color::Reset).to_string()),
+                }
+                bg = cell.bg;
+            }
+            output.push_str(&cell.text);
+            position = Some((x + cell.text.width(), y));
+        }
+
+        output.push_str(&format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset)));
+        output.push_str(&termion::cursor::Goto(self.cursor.x as u16 + 1, self.cursor.y as u16 + 1).to_string());
+        if self.cursor_visible == true {
+            output.push_str(termion::cursor::Show.as_ref());
+        }
+
+        self.last_frame.clone_from(&self.cells);
+        return output;
+    }
+}
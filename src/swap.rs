@@ -0,0 +1,116 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::io::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//Swap Files
+//while a document has unsaved changes, a copy of it is written every few seconds to a swap file so that the changes can be recovered if 
+//the editor dies before they are saved. The swap file for a named file sits next to it (".name.swp"), the one for a [No Name] buffer
+//goes in the state directory and is named after the process and the buffer, as there is no file to put it next to
+
+//how long the changes can go without being written to the swap file... also how long the terminal waits for input before saying so
+pub const SWAP_INTERVAL : Duration = Duration::from_secs(2);
+
+//Swap Id
+//tells apart the documents of one editor, so that each [No Name] buffer (the swap file shown next to its file for recovery being one)
+//has a swap file of its own. Every document is given the next one when it is created
+pub struct SwapId(usize);
+
+impl Default for SwapId {
+    fn default() -> Self {
+        static NEXT : AtomicUsize = AtomicUsize::new(0);
+        return Self(NEXT.fetch_add(1, Ordering::Relaxed));
+    }
+}
+
+pub fn path_for(file_name : Option<&str>, id : &SwapId) -> Option<PathBuf> {
+    match file_name {
+        Some(file_name) => return next_to(file_name),
+        None => {
+            return Some(state_dir()?.join(format!("no-name-{}-{}.swp", process::id(), id.0)));
+        }
+    }
+}
+
+fn next_to(file_name : &str) -> Option<PathBuf> {
+    let path = Path::new(file_name);
+    let name = path.file_name()?.to_string_lossy();
+    return Some(path.with_file_name(format!(".{}.swp", name)));
+}
+
+//$XDG_STATE_HOME/text_editor, falling back to ~/.local/state/text_editor
+pub fn state_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if dir.is_empty() == false => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+    };
+
+    return Some(base.join("text_editor"));
+}
+
+//the other name a swap file can go by (".name.swo" for ".name.swp" and back), which is written to instead while the first one holds
+//changes the user chose not to recover, so that they are not overwritten
+pub fn alternate(swap : &Path) -> PathBuf {
+    let extension = if swap.extension().map(|extension| extension == "swp") == Some(true) { "swo" } else { "swp" };
+    return swap.with_extension(extension);
+}
+
+//Recovery File
+//the swap file left behind for the file that is worth recovering, if any... the newest of its two names that was written after the
+//file was last changed on disk
+pub fn recovery_file(file_name : &str) -> Option<PathBuf> {
+    let swap = next_to(file_name)?;
+    let modified = |path : &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+    return [alternate(&swap), swap].into_iter()
+        .filter(|swap| is_newer(swap, Path::new(file_name)))
+        .max_by_key(|swap| modified(swap));
+}
+
+//a swap file is only worth recovering if it was written after the file was last changed on disk
+pub fn is_newer(swap : &Path, file : &Path) -> bool {
+    let modified = |path : &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+    return match (modified(swap), modified(file)) {
+        (Some(swap), Some(file)) => swap > file,
+        (Some(_), None) => true,
+        _ => false,
+    };
+}
+
+//Orphaned No Name
+//the swap file of a [No Name] buffer that was left behind by an editor that is no longer running (the newest one, if there are several).
+//A process that is still running (even as another user) is not asked for its swap file. The name holds the pid of the editor, followed
+//by the buffer's swap id
+pub fn orphaned_no_name() -> Option<PathBuf> {
+    let mut orphans : Vec<(PathBuf, SystemTime)> = Vec::new();
+
+    for entry in fs::read_dir(state_dir()?).ok()?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let pid = match name.strip_prefix("no-name-").and_then(|name| name.strip_suffix(".swp")).and_then(|name| name.split('-').next()).and_then(|pid| pid.parse::<libc::pid_t>().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        if is_running(pid) == false {
+            let modified = entry.metadata().and_then(|metadata| metadata.modified()).unwrap_or(UNIX_EPOCH);
+            orphans.push((entry.path(), modified));
+        }
+    }
+
+    return orphans.into_iter().max_by_key(|(_, modified)| *modified).map(|(path, _)| path);
+}
+
+//signal 0 only checks whether the process exists... EPERM means it does, but belongs to someone else. A pid of 0 or less would ask
+//about a whole process group instead, and no editor ever had one
+fn is_running(pid : libc::pid_t) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    if pid == process::id() as libc::pid_t || unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    return Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+}
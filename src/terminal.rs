@@ -1,99 +1,466 @@
 use crate::Position;
-use std::io::{self, stdout, Write};
-use termion::event::Key;
-use termion::input::TermRead;
+use crate::clipboard::Clipboard;
+use base64::Engine;
+use crate::screen::Screen;
+use crate::swap::SWAP_INTERVAL;
+use std::cell::{RefCell, RefMut};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, stdout, Error, ErrorKind, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::UnixStream;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Once, OnceLock};
+use termion::event::{Key, MouseEvent};
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::color;
 
+const ESCAPE : u8 = 0x1b;
+const PASTE_START : &[u8] = b"\x1b[200~";
+const PASTE_END : &[u8] = b"\x1b[201~";
+const ENABLE_BRACKETED_PASTE : &str = "\x1b[?2004h";
+const DISABLE_BRACKETED_PASTE : &str = "\x1b[?2004l";
+//clicks, drags (motion while a button is held) and the wheel, reported in the SGR format so columns past 223 work too
+const ENABLE_MOUSE : &str = "\x1b[?1000h\x1b[?1002h\x1b[?1006h";
+const DISABLE_MOUSE : &str = "\x1b[?1006l\x1b[?1002l\x1b[?1000l";
+
+//the terminal's settings from before it was put into raw mode, and whether it is still in the editor's hands... these are kept outside
+//of the TermionTerminal so that the panic hook can put the terminal back as well
+static ORIGINAL_MODE : OnceLock<libc::termios> = OnceLock::new();
+static ACTIVE : AtomicBool = AtomicBool::new(false);
+static ALTERNATE_SCREEN : AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK : Once = Once::new();
+
 pub struct Size {
     pub width   : u16,
     pub height  : u16,
 }
 
-pub struct  Terminal {
-    size    : Size,
-    _stdout : RawTerminal<std::io::Stdout>,
+//Event
+//something the user did: pressed a key, used the mouse, pasted a block of text into the terminal or resized its window. Pasted text
+//arrives as a whole (thanks to bracketed paste) rather than as one key press per character. Mouse positions start from (1, 1) at the 
+//top left, the way termion reports them. A Tick is sent when nothing has happened for SWAP_INTERVAL, so that changes typed just before
+//the user stopped still make it to the swap file
+#[derive(Clone)]
+pub enum Event {
+    Key(Key),
+    Mouse(MouseEvent),
+    Paste(String),
+    Resize,
+    Tick,
 }
 
-impl Terminal {
+//Terminal
+//what the editor draws on and gets its input from. Drawing goes into the frame of `screen`, which is the same for every backend, so a 
+//backend only has to say how big it is, where its events come from and what to do with a frame once it is finished
+pub trait Terminal {
 
-    pub fn default() -> Result<Self, std::io::Error> {
-        let size = termion::terminal_size()?;
-        Ok(Self {
-            size: Size {
-                width   : size.0,
-                height  : size.1.saturating_sub(2),
-            },
-            _stdout: stdout().into_raw_mode()?,
-        })
+    fn size(&self) -> &Size;
+
+    fn screen(&self) -> RefMut<'_, Screen>;
+
+    //waits for the next key press, mouse event, paste, resize or tick. The new size is known by the time a resize is returned. Input
+    //that has ended (or the editor being told to stop) is an UnexpectedEof error
+    fn read_event(&mut self) -> Result<Event, std::io::Error>;
+
+    //shows what was drawn since the last flush
+    fn flush(&self) -> Result<(), std::io::Error>;
+
+    //puts the terminal back the way it was before the editor started, when it has to stop without quitting normally
+    fn restore(&mut self) {}
+
+    //draws on the terminal's alternate screen (which is left again when the editor stops, bringing back what was on the terminal 
+    //before) or on its normal one
+    fn set_alternate_screen(&mut self, alternate_screen : bool);
+
+    //asks for mouse events, or leaves the mouse to the terminal (a backend without a mouse has nothing to do either way)
+    fn set_mouse(&mut self, _mouse : bool) {}
+
+    //the clipboard to copy to and paste from... the desktop's (or the terminal's), picked from the environment the editor runs in
+    fn clipboard(&self) -> Clipboard {
+        return Clipboard::default();
     }
 
-    //------------------------------------------------------------------------//
-    //--------------------------- Struct Getters -----------------------------//
-    //------------------------------------------------------------------------//
-    pub fn size(&self) -> &Size {
-        return &self.size;
+    //asks the terminal to put the text on the clipboard (a backend that cannot has nothing to do)
+    fn set_clipboard(&self, _text : &str) -> Result<(), std::io::Error> {
+        return Ok(());
+    }
+
+    //waits for the next key press, ignoring anything that is pasted in the meantime (a resize is not drawn until after the key press)
+    fn read_key(&mut self) -> Result<Key, std::io::Error> {
+        loop {
+            if let Event::Key(key) = self.read_event()? {
+                return Ok(key);
+            }
+        }
     }
 
     //------------------------------------------------------------------------//
     //------------------ Screen Management Functions -------------------------//
     //------------------------------------------------------------------------//
-    pub fn clear_screen() {
-        print!("{}", termion::clear::All);
+    //These draw into the frame that is being built rather than straight to the terminal... nothing shows up until flush is called
+
+    fn clear_screen(&self) {
+        self.screen().clear();
+    }
+
+    fn clear_current_line(&self) {
+        self.screen().clear_current_line();
+    }
+
+    fn cursor_position(&self, position : &Position) {
+        self.screen().move_cursor(position);
+    }
+
+    fn print(&self, text : &str) {
+        self.screen().put(text);
+    }
+
+    fn cursor_hide(&self) {
+        self.screen().set_cursor_visible(false);
+    }
+
+    fn cursor_show(&self) {
+        self.screen().set_cursor_visible(true);
+    }
+
+    fn set_bg_color(&self, color : color::Rgb) {
+        self.screen().set_bg(Some(color));
+    }
+
+    fn reset_bg_color(&self) {
+        self.screen().set_bg(None);
+    }
+
+    fn set_fg_color(&self, color : color::Rgb) {
+        self.screen().set_fg(Some(color));
+    }
+
+    fn reset_fg_color(&self) {
+        self.screen().set_fg(None);
+    }
+}
+
+//TermionTerminal
+//the terminal the editor is running in, put into raw mode (and bracketed paste) through termion for as long as the editor runs. The
+//RawTerminal is never dropped, as it panics if the terminal is gone by then... raw mode is left by putting back the saved mode instead
+pub struct  TermionTerminal {
+    size    : Size,
+    screen  : RefCell<Screen>,
+    input   : Input,
+    _stdout : ManuallyDrop<RawTerminal<std::io::Stdout>>,
+}
+
+//Input
+//the bytes read from the terminal that have not been turned into events yet. termion's key iterator reads ahead and throws away 
+//whatever it read ahead when it is dropped (losing key presses when typing quickly), so the bytes are kept here between calls instead.
+//stdin is read directly (not through the standard library's buffer) so that an escape byte arriving on its own can be told apart from
+//the start of an escape sequence. When the window is resized, the SIGWINCH handler writes to the `resized` pipe, and SIGHUP (the
+//terminal was closed) and SIGTERM write to the `stopped` one... both are watched along with stdin while waiting for input
+struct Input {
+    stdin   : ManuallyDrop<fs::File>,
+    pending : VecDeque<u8>,
+    resized : UnixStream,
+    stopped : UnixStream,
+}
+
+//what the wait for input ended with
+enum Wake {
+    Input,
+    Resized,
+    Stopped,
+    Timeout,
+}
+
+impl Input {
+
+    //Wait
+    //blocks until there is something to read from the terminal, the window has been resized or the editor has been told to stop, but
+    //no longer than SWAP_INTERVAL
+    fn wait(&mut self) -> Result<Wake, Error> {
+        let mut fds = [
+            libc::pollfd { fd: self.stdin.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: self.resized.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: self.stopped.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        ];
+
+        let ready = loop {
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, SWAP_INTERVAL.as_millis() as libc::c_int) };
+            if ready < 0 {
+                let error = Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            break ready;
+        };
+
+        if ready == 0 {
+            return Ok(Wake::Timeout);
+        }
+        if fds[2].revents & libc::POLLIN != 0 {
+            return Ok(Wake::Stopped);
+        }
+        if fds[1].revents & libc::POLLIN != 0 {
+            //several resizes in a row only need one redraw
+            let mut buffer = [0; 64];
+            while matches!(self.resized.read(&mut buffer), Ok(read) if read > 0) {}
+            return Ok(Wake::Resized);
+        }
+        return Ok(Wake::Input);
     }
 
-    pub fn clear_current_line() {
-        print!("{}", termion::clear::CurrentLine);
+    fn fill(&mut self) -> Result<(), Error> {
+        let mut buffer = [0; 4096];
+        let read = self.stdin.read(&mut buffer)?;
+        if read == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "end of input"));
+        }
+        self.pending.extend(&buffer[..read]);
+        return Ok(());
     }
 
-    pub fn cursor_position(position : &Position) {
-        let Position{mut x, mut y} = position;
-        x = x.saturating_add(1);
-        y = y.saturating_add(1);
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        if self.pending.is_empty() {
+            self.fill()?;
+        }
+        return Ok(self.pending.pop_front().unwrap_or_default());
+    }
 
-        let x = x as u16;
-        let y = y as u16;
+    //checks whether the pending bytes start with the sequence, reading more if what has arrived so far could be the start of it
+    fn starts_with(&mut self, sequence : &[u8]) -> Result<bool, Error> {
+        loop {
+            let len = std::cmp::min(self.pending.len(), sequence.len());
+            if self.pending.iter().take(len).ne(sequence[..len].iter()) {
+                return Ok(false);
+            }
+            if len == sequence.len() {
+                return Ok(true);
+            }
+            self.fill()?;
+        }
+    }
 
-        print!("{}", termion::cursor::Goto(x, y));
+    //Read Paste
+    //collects everything up to the end of the bracketed paste. Terminals send line breaks as '\r', which are turned into '\n', and
+    //any other control characters (apart from tabs) are dropped
+    fn read_paste(&mut self) -> Result<String, Error> {
+        self.pending.drain(..PASTE_START.len());
+        let mut text : Vec<u8> = Vec::new();
+
+        while text.ends_with(PASTE_END) == false {
+            text.push(self.next_byte()?);
+        }
+        text.truncate(text.len() - PASTE_END.len());
+
+        let text = String::from_utf8_lossy(&text).replace("\r\n", "\n").replace('\r', "\n");
+        return Ok(text.chars().filter(|c| *c == '\n' || *c == '\t' || c.is_control() == false).collect());
     }
+}
+
+impl TermionTerminal {
 
-    pub fn cursor_hide() {
-        print!("{}", termion::cursor::Hide);
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Result<Self, std::io::Error> {
+        let size = termion::terminal_size()?;
+        let (resized, resize_signal) = UnixStream::pair()?;
+        resized.set_nonblocking(true)?;
+        signal_hook::low_level::pipe::register(signal_hook::consts::SIGWINCH, resize_signal)?;
+        let (stopped, stop_signal) = UnixStream::pair()?;
+        signal_hook::low_level::pipe::register(signal_hook::consts::SIGHUP, stop_signal.try_clone()?)?;
+        signal_hook::low_level::pipe::register(signal_hook::consts::SIGTERM, stop_signal)?;
+        save_mode()?;
+        PANIC_HOOK.call_once(install_panic_hook);
+
+        Ok(Self {
+            size: Size {
+                width   : size.0,
+                height  : size.1.saturating_sub(1),
+            },
+            screen: RefCell::new(Screen::create(size.0 as usize, size.1 as usize)),
+            input: Input {
+                //the file is never dropped, so stdin is not closed
+                stdin   : ManuallyDrop::new(unsafe { fs::File::from_raw_fd(0) }),
+                pending : VecDeque::new(),
+                resized,
+                stopped,
+            },
+            _stdout: {
+                let stdout = stdout().into_raw_mode()?;
+                print!("{}", ENABLE_BRACKETED_PASTE);
+                ACTIVE.store(true, Ordering::SeqCst);
+                ManuallyDrop::new(stdout)
+            },
+        })
     }
 
-    pub fn cursor_show() {
-        print!("{}", termion::cursor::Show);
+    //reads the size of the window again after it has been resized (the last line is still kept for the message bar)
+    fn update_size(&mut self) -> Result<(), std::io::Error> {
+        let size = termion::terminal_size()?;
+        self.size = Size {
+            width   : size.0,
+            height  : size.1.saturating_sub(1),
+        };
+        self.screen = RefCell::new(Screen::create(size.0 as usize, size.1 as usize));
+        return Ok(());
     }
+}
 
-    pub fn flush() -> Result<(), std::io::Error> {
-        return io::stdout().flush();
+impl Terminal for TermionTerminal {
+
+    fn size(&self) -> &Size {
+        return &self.size;
     }
 
-    pub fn set_bg_color(color : color::Rgb) {
-        print!("{}", color::Bg(color));
+    fn screen(&self) -> RefMut<'_, Screen> {
+        return self.screen.borrow_mut();
     }
 
-    pub fn reset_bg_color() {
-        print!("{}", color::Bg(color::Reset));
+    //writes the changes made to the frame since the last flush to the terminal, all at once
+    fn flush(&self) -> Result<(), std::io::Error> {
+        let output = self.screen.borrow_mut().render();
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(output.as_bytes())?;
+        return stdout.flush();
     }
 
-    pub fn set_fg_color(color : color::Rgb) {
-        print!("{}", color::Fg(color));
+    fn restore(&mut self) {
+        restore_terminal();
     }
 
-    pub fn reset_fg_color() {
-        print!("{}", color::Fg(color::Reset));
+    fn set_alternate_screen(&mut self, alternate_screen : bool) {
+        if ALTERNATE_SCREEN.swap(alternate_screen, Ordering::SeqCst) == alternate_screen {
+            return;
+        }
+        if alternate_screen == true {
+            print!("{}", termion::screen::ToAlternateScreen);
+        } else {
+            print!("{}", termion::screen::ToMainScreen);
+        }
+        let _ = io::stdout().flush();
+        //the screen switched to does not show the last frame
+        self.screen.borrow_mut().invalidate();
+    }
+
+    fn set_mouse(&mut self, mouse : bool) {
+        print!("{}", if mouse == true { ENABLE_MOUSE } else { DISABLE_MOUSE });
+        let _ = io::stdout().flush();
+    }
+
+    //with the OSC 52 escape sequence, which the terminal answers by setting the clipboard of the machine it runs on
+    fn set_clipboard(&self, text : &str) -> Result<(), std::io::Error> {
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "\x1b]52;c;{}\x07", base64::engine::general_purpose::STANDARD.encode(text))?;
+        return stdout.flush();
     }
 
     //------------------------------------------------------------------------//
     //-------------------- Keyboard Input Management -------------------------//
     //------------------------------------------------------------------------//
-    pub fn read_key() -> Result<Key, std::io::Error> {
+    //Read Event
+    //waits for the next key press, mouse event, paste or resize. Byte sequences that are neither keys nor mouse events (or that termion 
+    //does not understand) are skipped. The new size of the window is known by the time a resize is returned. Being told to stop is 
+    //treated like the input ending, as nothing more is going to be typed either way
+    fn read_event(&mut self) -> Result<Event, std::io::Error> {
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+            if self.input.pending.is_empty() {
+                match self.input.wait()? {
+                    Wake::Input => self.input.fill()?,
+                    Wake::Resized => {
+                        self.update_size()?;
+                        return Ok(Event::Resize);
+                    }
+                    Wake::Stopped => return Err(Error::new(ErrorKind::UnexpectedEof, "stopped by a signal")),
+                    Wake::Timeout => return Ok(Event::Tick),
+                }
+            }
+
+            //an escape byte with nothing after it, or just "\x1b[", is the Esc key (or Alt-[), not the start of a paste
+            if self.input.pending.len() > 2 && self.input.starts_with(PASTE_START)? {
+                return Ok(Event::Paste(self.input.read_paste()?));
+            }
+
+            let byte = self.input.next_byte()?;
+            if byte == ESCAPE && self.input.pending.is_empty() {
+                return Ok(Event::Key(Key::Esc));
+            }
+
+            let input = &mut self.input;
+            match termion::event::parse_event(byte, &mut std::iter::from_fn(|| Some(input.next_byte()))) {
+                Ok(termion::event::Event::Key(key)) => return Ok(Event::Key(key)),
+                Ok(termion::event::Event::Mouse(mouse)) => return Ok(Event::Mouse(mouse)),
+                _ => (),
             }
         }
     }
+}
 
-}
\ No newline at end of file
+//the terminal may already be gone (after a SIGHUP), so failing to write to it is ignored
+impl Drop for TermionTerminal {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::SeqCst);
+        let mut stdout = io::stdout().lock();
+        if ALTERNATE_SCREEN.swap(false, Ordering::SeqCst) == true {
+            let _ = write!(stdout, "{}{}", termion::screen::ToMainScreen, termion::cursor::Show);
+        }
+        let _ = write!(stdout, "{}{}", DISABLE_MOUSE, DISABLE_BRACKETED_PASTE);
+        let _ = stdout.flush();
+        if let Some(mode) = ORIGINAL_MODE.get() {
+            unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSADRAIN, mode) };
+        }
+    }
+}
+
+//------------------------------------------------------------------------//
+//------------------------- Restoring The Terminal -----------------------//
+//------------------------------------------------------------------------//
+
+//keeps the terminal's settings from before raw mode (the first time only, as a later terminal would find raw mode already on)
+fn save_mode() -> Result<(), std::io::Error> {
+    if ORIGINAL_MODE.get().is_some() {
+        return Ok(());
+    }
+    let mut mode : libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut mode) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    let _ = ORIGINAL_MODE.set(mode);
+    return Ok(());
+}
+
+//Panic Hook
+//a panic is reported before anything is unwound (so before the RawTerminal could put the terminal back), which would leave the message
+//scattered across a raw, half drawn screen with no cursor. The terminal is restored first, and the message printed as usual after it
+fn install_panic_hook() {
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        report(info);
+    }));
+}
+
+//Restore Terminal
+//puts the terminal back the way it was before the editor started: colours and the cursor are reset, the alternate screen is left (or
+//the screen cleared, without it), mouse reporting and bracketed paste are turned off and raw mode is left, so whatever is printed next starts on a clean
+//line and reads normally. Only the first call does anything, and none do once the editor has shut down normally
+fn restore_terminal() {
+    if ACTIVE.swap(false, Ordering::SeqCst) == false {
+        return;
+    }
+
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
+    if ALTERNATE_SCREEN.swap(false, Ordering::SeqCst) == true {
+        let _ = write!(stdout, "{}", termion::screen::ToMainScreen);
+    } else {
+        let _ = write!(stdout, "{}{}", termion::clear::All, termion::cursor::Goto(1, 1));
+    }
+    let _ = write!(stdout, "{}{}{}", termion::cursor::Show, DISABLE_MOUSE, DISABLE_BRACKETED_PASTE);
+    let _ = stdout.flush();
+    if let Some(mode) = ORIGINAL_MODE.get() {
+        unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSADRAIN, mode) };
+    }
+}
@@ -0,0 +1,13 @@
+use std::fs;
+use std::path::PathBuf;
+
+//a file in a directory of its own for the test, so swap files written next to it do not get in the way of other tests (the
+//directory is named after the test binary as well, as the binaries run alongside each other)
+pub fn test_file(test : &str, contents : impl AsRef<[u8]>) -> String {
+    let directory : PathBuf = std::env::temp_dir().join(format!("text_editor_{}_{}", env!("CARGO_CRATE_NAME"), test));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("file.txt");
+    fs::write(&path, contents).unwrap();
+    return path.to_string_lossy().into_owned();
+}
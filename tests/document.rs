@@ -0,0 +1,259 @@
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use regex::Regex;
+use text_editor::{Document, LineEnding, Position, SearchDirection};
+
+mod common;
+use common::test_file;
+
+fn rows(document : &Document) -> Vec<String> {
+    return (0..document.len())
+        .map(|index| String::from_utf8(document.row(index).unwrap().as_bytes().to_vec()).unwrap())
+        .collect();
+}
+
+#[test]
+fn redo_gives_back_the_same_rows() {
+    let file = test_file("redo_newline", b"a\n");
+    let mut document = Document::open(&file).unwrap();
+
+    document.insert(&Position { x: 0, y: 1 }, '\n');
+    assert_eq!(rows(&document), ["a", ""]);
+    document.undo();
+    assert_eq!(rows(&document), ["a"]);
+    document.redo();
+    assert_eq!(rows(&document), ["a", ""]);
+
+    document.insert(&Position { x: 0, y: 2 }, 'b');
+    document.undo();
+    document.redo();
+    assert_eq!(rows(&document), ["a", "", "b"]);
+}
+
+#[test]
+fn edits_land_on_grapheme_boundaries_in_long_rows() {
+    //four graphemes per unit, two of them made of several chars, repeated across many of the rope's chunks
+    let unit = ["a", "b", "e\u{301}", "\u{1F44D}\u{1F3FD}"];
+    let line : String = unit.concat().repeat(2000);
+    let file = test_file("long_row", format!("{}\n", line));
+    let mut document = Document::open(&file).unwrap();
+
+    for x in [0, 1, 2, 3, 4, 4001, 4002, 4003, 7999, 8000] {
+        document.insert(&Position { x, y: 0 }, '|');
+        let mut expected : Vec<&str> = unit.iter().copied().cycle().take(8000).collect();
+        expected.insert(x, "|");
+        assert_eq!(rows(&document)[0], expected.concat(), "inserting at {}", x);
+
+        document.delete(&Position { x, y: 0 });
+        assert_eq!(rows(&document)[0], line, "deleting at {}", x);
+    }
+
+    document.delete(&Position { x: 2, y: 0 });
+    assert!(rows(&document)[0].starts_with("ab\u{1F44D}\u{1F3FD}a"));
+    assert_eq!(document.row(0).unwrap().get_len(), 7999);
+}
+
+#[test]
+fn rows_are_measured_the_same_without_building_them() {
+    //tabs, wide and combining graphemes spread across many of the rope's chunks
+    let unit = ["a", "\t", "e\u{301}", "\u{1F44D}\u{1F3FD}", "\u{4E2D}", "\u{1}"];
+    let line : String = unit.concat().repeat(1000);
+    let file = test_file("measures", format!("{}\nshort\n", line));
+    let document = Document::open(&file).unwrap();
+    let row = document.row(0).unwrap();
+
+    assert_eq!(document.row_len(0), row.get_len());
+    assert_eq!(document.row_len(1), 5);
+    assert_eq!(document.row_len(2), 0);
+    assert_eq!(document.graphemes(0).collect::<String>(), line);
+    assert_eq!(document.width(0, 4), row.width(4));
+    for x in [0, 1, 2, 3, 5, 6, 7, 2999, 5999, 6000, 6005] {
+        assert_eq!(document.column(0, x, 4), row.column(x, 4), "column of {}", x);
+    }
+    for column in [0, 1, 2, 4, 5, 6, 7, 8, 11, 12, 9000, row.width(4) - 1, row.width(4) + 3] {
+        assert_eq!(document.index_at(0, column, 4), row.index_at(column, 4), "index at {}", column);
+    }
+    assert_eq!(document.column(2, 3, 4), 3);
+}
+
+#[test]
+fn rendering_far_along_a_row() {
+    //five graphemes and six columns per unit, so the wide grapheme is cut in half by some of the columns the row is drawn from
+    let file = test_file("render", format!("{}\n", "abcd\u{4E2D}".repeat(1000)));
+    let document = Document::open(&file).unwrap();
+    let text = |start : usize, end : usize| document.render(0, start, end, 4, None).into_iter().map(|(_, _, text)| text).collect::<String>();
+
+    assert_eq!(text(0, 6), "abcd\u{4E2D}");
+    assert_eq!(text(6 * 700 + 1, 6 * 700 + 7), "bcd\u{4E2D}a");
+    assert_eq!(text(6 * 700 + 5, 6 * 700 + 7), " a");
+    assert_eq!(text(6 * 999 + 4, 7000), "\u{4E2D}");
+
+    let row = document.row(0).unwrap();
+    assert_eq!(row.index_at(6 * 700 + 5, 4), 5 * 700 + 4);
+    assert_eq!(row.column(5 * 700 + 4, 4), 6 * 700 + 4);
+    assert!(document.has_grapheme(0, 4999));
+    assert!(document.has_grapheme(0, 5000) == false);
+}
+
+#[test]
+fn drawing_far_along_a_row_does_not_walk_it_from_the_start() {
+    let file = test_file("render_scrolled", format!("{}\n", "abcd\u{4E2D}".repeat(200_000)));
+    let mut document = Document::open(&file).unwrap();
+    let text = |document : &Document, index : usize, start : usize, end : usize| {
+        return document.render(index, start, end, 4, None).into_iter().map(|(_, _, text)| text).collect::<String>();
+    };
+
+    //the first time the row is walked all the way, after that the walk starts close to where it is drawn from
+    let started = Instant::now();
+    for _ in 0..1000 {
+        assert_eq!(text(&document, 0, 6 * 199_999, 6 * 200_000), "abcd\u{4E2D}");
+    }
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    //an edit moves what comes after it, but not what comes before it
+    document.insert(&Position { x: 5 * 500, y: 0 }, '\t');
+    assert_eq!(text(&document, 0, 6 * 100, 6 * 101), "abcd\u{4E2D}");
+    assert_eq!(text(&document, 0, 6 * 199_999 + 4, 6 * 200_000 + 4), "abcd\u{4E2D}");
+    assert_eq!(document.column(0, 5 * 199_999 + 1, 4), 6 * 199_999 + 4);
+    assert_eq!(document.index_at(0, 6 * 199_999 + 4, 4), 5 * 199_999 + 1);
+    assert_eq!(document.width(0, 4), 6 * 200_000 + 4);
+    //and a new row moves the rows below it
+    document.insert(&Position { x: 0, y: 0 }, '\n');
+    assert_eq!(text(&document, 0, 0, 6), "");
+    assert_eq!(text(&document, 1, 6 * 199_999 + 4, 6 * 200_000 + 4), "abcd\u{4E2D}");
+}
+
+#[test]
+fn saving_an_untouched_file_gives_back_the_same_bytes() {
+    let files : [(&str, &[u8]); 8] = [
+        ("lf", b"a\nb\n"),
+        ("crlf", b"a\r\nb\r\n"),
+        ("no_final_newline", b"a\nb"),
+        ("crlf_no_final_newline", b"a\r\nb"),
+        ("bom", b"\xEF\xBB\xBFa\r\nb\r\n"),
+        ("mixed_lf_first", b"a\nb\r\nc\n"),
+        ("mixed_crlf_first", b"a\r\nb\nc\r\n"),
+        ("stray_cr", b"a\rb\r\nc\r"),
+    ];
+
+    for (name, contents) in files {
+        let file = test_file(&format!("round_trip_{}", name), contents);
+        let mut document = Document::open(&file).unwrap();
+        document.save().unwrap();
+        assert_eq!(fs::read(&file).unwrap(), contents, "{}", name);
+    }
+}
+
+#[test]
+fn saving_keeps_the_permissions_and_leaves_other_temporary_files_alone() {
+    let file = test_file("save_permissions", b"a\n");
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+    let mut document = Document::open(&file).unwrap();
+    document.insert(&Position { x: 1, y: 0 }, 'b');
+    document.save().unwrap();
+    assert_eq!(fs::read(&file).unwrap(), b"ab\n");
+    assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+
+    //a temporary file by the same name that is already there stops the save, and is left as it was
+    let temporary = PathBuf::from(&file).with_file_name(format!(".file.txt.{}.tmp", std::process::id()));
+    fs::write(&temporary, "other").unwrap();
+    assert!(document.save().is_err());
+    assert_eq!(fs::read_to_string(&temporary).unwrap(), "other");
+    assert_eq!(fs::read(&file).unwrap(), b"ab\n");
+}
+
+#[test]
+fn mixed_line_endings_keep_their_carriage_returns() {
+    let file = test_file("mixed_rows", b"a\r\nb\nc\r\n");
+    let document = Document::open(&file).unwrap();
+    assert_eq!(rows(&document), ["a\r", "b", "c\r"]);
+    assert!(document.format().line_ending == LineEnding::Lf);
+
+    let file = test_file("crlf_rows", b"a\r\nb\r\n");
+    let document = Document::open(&file).unwrap();
+    assert_eq!(rows(&document), ["a", "b"]);
+    assert!(document.format().line_ending == LineEnding::CrLf);
+}
+
+#[test]
+fn replace_returns_the_grapheme_after_the_replacement() {
+    let file = test_file("replace", b"aa\n");
+    let mut document = Document::open(&file).unwrap();
+
+    let after = document.replace(&Position { x: 0, y: 0 }, 1, "e\u{301}");
+    assert_eq!(after, Position { x: 1, y: 0 });
+    let after = document.replace(&after, 1, "e\u{301}\nb");
+    assert_eq!(after, Position { x: 1, y: 1 });
+    assert_eq!(rows(&document), ["e\u{301}e\u{301}", "b"]);
+
+    document.undo();
+    assert_eq!(rows(&document), ["e\u{301}a"]);
+}
+
+#[test]
+fn every_match_in_a_row_is_found_in_one_go() {
+    let file = test_file("find_regex", format!("e\u{301}e ab\n{}\n", "ab ".repeat(200_000)));
+    let document = Document::open(&file).unwrap();
+
+    //the first "e" is cut short of its accent, so only the second one lines up with a grapheme
+    let found = document.find_regex(&Regex::new("e").unwrap(), 0, "x");
+    assert_eq!(found, [(1, 1, "x".to_string())]);
+    let found = document.find_regex(&Regex::new("(a)(b)").unwrap(), 0, "$2$1");
+    assert_eq!(found, [(3, 2, "ba".to_string())]);
+    let found = document.find_regex(&Regex::new("x*").unwrap(), 0, "");
+    assert_eq!(found.iter().map(|(x, _, _)| *x).collect::<Vec<usize>>(), [0, 1, 2, 3, 4, 5]);
+
+    let started = Instant::now();
+    let found = document.find_regex(&Regex::new("b").unwrap(), 1, "c");
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(found.len(), 200_000);
+    assert_eq!(found.last(), Some(&(599_998, 1, "c".to_string())));
+}
+
+#[test]
+fn searching_reads_the_rows_in_place() {
+    let file = test_file("find", format!("e\u{301}e ab\n{}\nab\n", "ab ".repeat(200_000)));
+    let document = Document::open(&file).unwrap();
+    let start = Position { x: 0, y: 0 };
+
+    //a match has to start on a grapheme, but not end on one
+    assert_eq!(document.find("e", &start, SearchDirection::Forward), Some(start));
+    assert_eq!(document.find("\u{301}", &start, SearchDirection::Forward), None);
+    assert_eq!(document.find("e", &Position { x: 2, y: 0 }, SearchDirection::Backward), Some(Position { x: 1, y: 0 }));
+    assert_eq!(document.find("e", &Position { x: 1, y: 0 }, SearchDirection::Backward), Some(start));
+    assert_eq!(document.find("ab", &Position { x: 4, y: 0 }, SearchDirection::Forward), Some(Position { x: 0, y: 1 }));
+    assert_eq!(document.find("ab", &start, SearchDirection::Backward), Some(Position { x: 0, y: 2 }));
+
+    //searching on from far along a long row does not copy the row every time, and starts from where drawing the row left off
+    document.column(1, 599_992, 4);
+    let started = Instant::now();
+    for _ in 0..1000 {
+        let found = document.find("ab", &Position { x: 599_992, y: 1 }, SearchDirection::Forward);
+        assert_eq!(found, Some(Position { x: 599_994, y: 1 }));
+    }
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn a_swap_file_that_could_not_be_written_is_written_the_next_time() {
+    let file = test_file("swap_retry", b"text\n");
+    let swap = PathBuf::from(&file).with_file_name(".file.txt.swp");
+    let mut document = Document::open(&file).unwrap();
+    document.insert(&Position { x: 0, y: 0 }, 'a');
+
+    //a directory in the way of the swap file makes the write fail
+    fs::create_dir(&swap).unwrap();
+    assert!(document.write_swap_file().is_err());
+    fs::remove_dir(&swap).unwrap();
+    document.write_swap_file().unwrap();
+    assert_eq!(fs::read_to_string(&swap).unwrap(), "atext\n");
+
+    //the swap file is replaced by a new one rather than written over, so a write that is cut short leaves the last one whole
+    let before = fs::metadata(&swap).unwrap().ino();
+    document.insert(&Position { x: 0, y: 0 }, 'b');
+    document.write_swap_file().unwrap();
+    assert_eq!(fs::read_to_string(&swap).unwrap(), "batext\n");
+    assert!(fs::metadata(&swap).unwrap().ino() != before);
+}
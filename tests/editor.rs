@@ -0,0 +1,504 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::Once;
+use std::time::{Duration, Instant};
+use termion::event::{Key, MouseButton, MouseEvent};
+use text_editor::{typed, Config, Editor, Event, HeadlessTerminal, LineEnding, Position};
+
+mod common;
+use common::test_file;
+
+static STATE_DIR : Once = Once::new();
+
+fn run(files : &[&str], config : Config, events : Vec<Event>) -> Editor<HeadlessTerminal> {
+    //swap files of [No Name] buffers (and the ones left behind by other editors) are kept away from the user's own
+    STATE_DIR.call_once(|| std::env::set_var("XDG_STATE_HOME", std::env::temp_dir().join("text_editor_state")));
+    let args : Vec<String> = files.iter().map(|file| file.to_string()).collect();
+    let mut editor = Editor::create(HeadlessTerminal::create(100, 8, events), &args, config, &[]);
+    editor.run();
+    return editor;
+}
+
+//a configuration with the vim keys turned on
+fn vim_config() -> Config {
+    let mut config = Config::default();
+    config.set("vim_mode=true").unwrap();
+    return config;
+}
+
+fn row_text(editor : &Editor<HeadlessTerminal>, index : usize) -> String {
+    let row = editor.document().row(index).unwrap();
+    return String::from_utf8(row.as_bytes().to_vec()).unwrap();
+}
+
+#[test]
+fn shows_the_welcome_message_without_a_file() {
+    let editor = run(&[], Config::default(), Vec::new());
+    let lines = editor.terminal().lines();
+    assert!(lines[0].starts_with('~'));
+    assert!(lines.iter().any(|line| line.contains("Text Editor -- version")));
+    assert!(lines[7].starts_with("HELP: Ctrl-W = write"));
+}
+
+#[test]
+fn quitting_leaves_the_alternate_screen() {
+    let editor = run(&[], Config::default(), vec![Event::Key(Key::Alt('q'))]);
+    assert!(editor.terminal().is_alternate_screen());
+    assert!(editor.terminal().lines().iter().all(|line| line.starts_with("Goodbye.") == false));
+
+    let mut config = Config::default();
+    config.set("noalternate_screen").unwrap();
+    let editor = run(&[], config, vec![Event::Key(Key::Alt('q'))]);
+    assert!(editor.terminal().is_alternate_screen() == false);
+    assert!(editor.terminal().line(0).starts_with("Goodbye."));
+}
+
+#[test]
+fn typing_changes_the_document_and_the_screen() {
+    let file = test_file("typing", "world\n");
+    let editor = run(&[&file], Config::default(), typed("hello \nnew"));
+
+    assert_eq!(row_text(&editor, 0), "hello ");
+    assert_eq!(row_text(&editor, 1), "newworld");
+    assert!(editor.document().is_dirty());
+    assert_eq!(editor.cursor_position(), Position { x: 3, y: 1 });
+
+    let terminal = editor.terminal();
+    assert!(terminal.line(0).starts_with("hello "));
+    assert!(terminal.line(1).starts_with("newworld"));
+    assert!(terminal.line(6).contains("(modified)"));
+    assert_eq!(terminal.cursor(), Position { x: 3, y: 1 });
+}
+
+#[test]
+fn saving_writes_the_file() {
+    let file = test_file("saving", "one\ntwo\n");
+    let mut events = vec![Event::Key(Key::Alt('f')), Event::Key(Key::Alt('k')), Event::Key(Key::Alt('k')), Event::Key(Key::Alt('k'))];
+    events.extend(typed("!"));
+    events.push(Event::Key(Key::Ctrl('w')));
+    let editor = run(&[&file], Config::default(), events);
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one\ntwo!\n");
+    assert!(editor.document().is_dirty() == false);
+    assert!(editor.status_message().starts_with("File saved successfully"));
+}
+
+#[test]
+fn undo_and_redo() {
+    let file = test_file("undo", "text\n");
+    let mut events = typed("abc");
+    events.push(Event::Key(Key::Ctrl('z')));
+    let editor = run(&[&file], Config::default(), events.clone());
+    assert_eq!(row_text(&editor, 0), "text");
+    assert!(editor.document().is_dirty() == false);
+
+    events.push(Event::Key(Key::Ctrl('y')));
+    let editor = run(&[&file], Config::default(), events);
+    assert_eq!(row_text(&editor, 0), "abctext");
+}
+
+#[test]
+fn undoing_back_to_the_saved_text_is_not_a_change() {
+    //every run saves, so each one starts again from a new file
+    let run_from_start = |events : &[Event]| run(&[&test_file("undo_saved", "text\n")], Config::default(), events.to_vec());
+    let mut events = typed("ab");
+    events.push(Event::Key(Key::Ctrl('w')));
+    events.extend(typed("c"));
+    events.push(Event::Key(Key::Ctrl('z')));
+    let editor = run_from_start(&events);
+    assert_eq!(row_text(&editor, 0), "abtext");
+    assert!(editor.document().is_dirty() == false);
+
+    events.push(Event::Key(Key::Ctrl('z')));
+    let editor = run_from_start(&events);
+    assert_eq!(row_text(&editor, 0), "text");
+    assert!(editor.document().is_dirty());
+    assert!(editor.terminal().line(6).contains("(modified)"));
+
+    events.push(Event::Key(Key::Ctrl('y')));
+    let editor = run_from_start(&events);
+    assert_eq!(row_text(&editor, 0), "abtext");
+    assert!(editor.document().is_dirty() == false);
+
+    events.push(Event::Key(Key::Ctrl('y')));
+    let editor = run_from_start(&events);
+    assert_eq!(row_text(&editor, 0), "abctext");
+    assert!(editor.document().is_dirty());
+}
+
+#[test]
+fn typing_into_a_very_long_row_only_draws_what_is_shown() {
+    //building the whole row for every key took seconds per key on a row this long, drawing only the visible part takes no time
+    let line = "abc\u{4E2D}e\u{301} ".repeat(1 << 19);
+    let file = test_file("long_row", format!("{}\n", line));
+    let started = Instant::now();
+    let editor = run(&[&file], Config::default(), typed(&"x".repeat(200)));
+
+    assert!(started.elapsed() < Duration::from_secs(10), "took {:?}", started.elapsed());
+    assert_eq!(editor.document().row_len(0), 200 + 6 * (1 << 19));
+    assert_eq!(editor.cursor_position(), Position { x: 200, y: 0 });
+    //the view follows the cursor, which is on the last column
+    assert_eq!(editor.terminal().line(0), format!("{}a", "x".repeat(99)));
+}
+
+#[test]
+fn pasting_inserts_the_text_at_once() {
+    let file = test_file("pasting", "\n");
+    let editor = run(&[&file], Config::default(), vec![Event::Paste("first\nsecond".to_string())]);
+
+    assert_eq!(row_text(&editor, 0), "first");
+    assert_eq!(row_text(&editor, 1), "second");
+    assert_eq!(editor.cursor_position(), Position { x: 6, y: 1 });
+}
+
+#[test]
+fn opens_files_at_a_line() {
+    let contents : String = (1..=30).map(|line| format!("line {}\n", line)).collect();
+    let file = test_file("location", &contents);
+    let editor = run(&[&format!("{}:20:3", file)], Config::default(), Vec::new());
+
+    assert_eq!(editor.cursor_position(), Position { x: 2, y: 19 });
+    assert!(editor.terminal().lines().iter().any(|line| line.starts_with("line 20")));
+    assert!(editor.terminal().line(6).contains("20/30"));
+}
+
+#[test]
+fn replace_says_why_it_did_not_finish() {
+    let file = test_file("replace_messages", "a a a\n");
+    let mut events = vec![Event::Key(Key::Ctrl('r'))];
+    events.extend(typed("(\n"));
+    let editor = run(&[&file], Config::default(), events);
+    assert_eq!(editor.status_message(), "ERR: invalid pattern (: unclosed group");
+
+    let mut events = vec![Event::Key(Key::Ctrl('r'))];
+    events.extend(typed("a\nb\nyq"));
+    let editor = run(&[&file], Config::default(), events);
+    assert_eq!(row_text(&editor, 0), "b a a");
+    assert_eq!(editor.status_message(), "Replace stopped after 1 occurrence(s).");
+}
+
+#[test]
+fn replace_asks_about_each_occurrence() {
+    let file = test_file("replace_interactive", "a a a a\na\n");
+    let mut events = vec![Event::Key(Key::Ctrl('r'))];
+    events.extend(typed("a\nb\nnya"));
+    let editor = run(&[&file], Config::default(), events);
+    assert_eq!(row_text(&editor, 0), "a b b b");
+    assert_eq!(row_text(&editor, 1), "b");
+    assert_eq!(editor.status_message(), "Replaced 4 occurrence(s).");
+
+    //the first run left its changes in a swap file, which would be offered for recovery
+    let file = test_file("replace_interactive", "a a a a\na\n");
+    let mut events = vec![Event::Key(Key::Ctrl('r'))];
+    events.extend(typed("a\nb\nyn"));
+    events.push(Event::Key(Key::Esc));
+    let editor = run(&[&file], Config::default(), events);
+    assert_eq!(row_text(&editor, 0), "b a a a");
+    assert_eq!(editor.status_message(), "Replace stopped after 1 occurrence(s).");
+}
+
+#[test]
+fn line_endings_are_only_converted_by_name() {
+    //rewriting every line ending is not bound to a key, so it cannot happen by a stray key press
+    let file = test_file("line_endings", "a\nb\n");
+    let editor = run(&[&file], Config::default(), vec![Event::Key(Key::Ctrl('e'))]);
+    assert!(editor.document().format().line_ending == LineEnding::Lf);
+    assert!(editor.document().is_dirty() == false);
+
+    let mut events = vec![Event::Key(Key::Alt('x'))];
+    events.extend(typed("toggle_line_ending\n"));
+    let editor = run(&[&file], Config::default(), events);
+    assert!(editor.document().format().line_ending == LineEnding::CrLf);
+    assert!(editor.document().is_dirty());
+}
+
+#[test]
+fn vim_mode_commands() {
+    let file = test_file("vim", "first line\nsecond line\nthird line\n");
+    let mut events = typed("jddwiNEW ");
+    events.push(Event::Key(Key::Esc));
+    let editor = run(&[&file], vim_config(), events);
+
+    assert_eq!(row_text(&editor, 0), "first line");
+    assert_eq!(row_text(&editor, 1), "third NEW line");
+    assert_eq!(editor.document().len(), 2);
+}
+
+#[test]
+fn vim_puts_back_what_was_deleted() {
+    let file = test_file("vim_register", "ab\nline\n");
+    let editor = run(&[&file], vim_config(), typed("xpjddkP"));
+
+    assert_eq!(row_text(&editor, 0), "line");
+    assert_eq!(row_text(&editor, 1), "ba");
+    assert_eq!(editor.document().len(), 2);
+}
+
+#[test]
+fn copying_stays_inside_a_headless_editor() {
+    let file = test_file("copy", "line\n");
+    let editor = run(&[&file], vim_config(), typed("yyp"));
+
+    assert_eq!(row_text(&editor, 1), "line");
+    assert!(editor.status_message().ends_with("to the clipboard (internal register)."));
+}
+
+#[test]
+fn vim_deleting_nothing_keeps_the_register() {
+    let file = test_file("vim_empty_delete", "ab\n\n");
+    let editor = run(&[&file], vim_config(), typed("xjxd0dwp"));
+
+    assert_eq!(row_text(&editor, 0), "b");
+    assert_eq!(row_text(&editor, 1), "a");
+}
+
+#[test]
+fn vim_counts_too_large_to_use_are_capped() {
+    let file = test_file("vim_counts", "ab\ncd\n");
+    let editor = run(&[&file], vim_config(), typed("99999999999999999999j99999999999l2d99999999999999jylx99999999999p"));
+
+    assert_eq!(editor.document().len(), 1);
+    assert_eq!(editor.document().row(0).unwrap().get_len(), 100_001);
+}
+
+#[test]
+fn vim_editing_keys_stay_out_of_normal_mode() {
+    let file = test_file("vim_keys", "abc\n");
+    let mut events = typed("ll");
+    events.extend([Key::Backspace, Key::Delete].map(Event::Key));
+    //something on the clipboard that Ctrl-V would paste
+    events.extend(typed("yl"));
+    events.extend([Key::Ctrl('x'), Key::Ctrl('v')].map(Event::Key));
+    let editor = run(&[&file], vim_config(), events);
+
+    assert_eq!(row_text(&editor, 0), "ac");
+    assert_eq!(editor.cursor_position(), Position { x: 1, y: 0 });
+    assert_eq!(editor.document().len(), 1);
+}
+
+#[test]
+fn vim_keeps_the_cursor_on_a_grapheme() {
+    //every run gets a file of its own, as the changes each one leaves behind would be offered for recovery
+    let file = |name : &str| test_file(name, "abc\nlonger line\nxy\n");
+    let editor = run(&[&file("vim_row_end_1")], vim_config(), typed("llll"));
+    assert_eq!(editor.cursor_position(), Position { x: 2, y: 0 });
+
+    let editor = run(&[&file("vim_row_end_2")], vim_config(), typed("$x"));
+    assert_eq!(row_text(&editor, 0), "ab");
+    assert_eq!(editor.cursor_position(), Position { x: 1, y: 0 });
+
+    let editor = run(&[&file("vim_row_end_3")], vim_config(), typed("j$j"));
+    assert_eq!(editor.cursor_position(), Position { x: 1, y: 2 });
+
+    let editor = run(&[&file("vim_row_end_4")], vim_config(), typed("$dl"));
+    assert_eq!(row_text(&editor, 0), "ab");
+
+    //j and k move by screen lines when the row is wrapped, like the arrow key actions
+    let wrapped = test_file("vim_wrapped", format!("{}\nb\n", "a".repeat(150)));
+    let mut events = vec![Event::Key(Key::Alt('z'))];
+    events.extend(typed("j"));
+    let editor = run(&[&wrapped], vim_config(), events);
+    assert_eq!(editor.cursor_position(), Position { x: 100, y: 0 });
+}
+
+#[test]
+fn python_strings_in_triple_quotes_carry_on_across_rows() {
+    let file = PathBuf::from(test_file("python_strings", "")).with_file_name("file.py");
+    fs::write(&file, "x = '''one\n\"\"\" two\n''' + 1\n").unwrap();
+    let editor = run(&[file.to_str().unwrap()], Config::default(), Vec::new());
+
+    let terminal = editor.terminal().frame();
+    let string = terminal.foreground(4, 0);
+    assert!(string.is_some());
+    assert_eq!(terminal.foreground(0, 1), string);
+    assert_eq!(terminal.foreground(4, 1), string);
+    assert_eq!(terminal.foreground(2, 2), string);
+    assert_eq!(terminal.foreground(4, 2), None);
+    assert_ne!(terminal.foreground(6, 2), string);
+}
+
+#[test]
+fn split_panes_show_the_same_document() {
+    let file = test_file("split", "text\n");
+    let mut events = vec![Event::Key(Key::Alt('w')), Event::Key(Key::Char('s'))];
+    events.extend(typed("new "));
+    let editor = run(&[&file], Config::default(), events);
+
+    assert_eq!(row_text(&editor, 0), "new text");
+    let shown = editor.terminal().lines().iter().filter(|line| line.starts_with("new text")).count();
+    assert_eq!(shown, 2);
+}
+
+#[test]
+fn comments_over_several_rows_are_highlighted() {
+    let file = PathBuf::from(test_file("block_comment", "")).with_file_name("file.rs");
+    fs::write(&file, "a /* one\ntwo */ b\nc\n").unwrap();
+    let mut events = vec![Event::Key(Key::Alt('f')), Event::Key(Key::Alt('f'))];
+    events.extend(typed("/* "));
+    let editor = run(&[file.to_str().unwrap()], Config::default(), events);
+
+    let terminal = editor.terminal().frame();
+    let comment = terminal.foreground(2, 0);
+    assert!(comment.is_some());
+    assert_eq!(terminal.foreground(0, 0), None);
+    assert_eq!(terminal.foreground(5, 0), comment);
+    assert_eq!(terminal.foreground(0, 1), comment);
+    assert_eq!(terminal.foreground(5, 1), comment);
+    assert_eq!(terminal.foreground(7, 1), None);
+    //the comment opened while editing carries on to the end of the document
+    assert_eq!(terminal.foreground(3, 2), comment);
+}
+
+#[test]
+fn rust_strings_over_several_rows_are_highlighted() {
+    let file = PathBuf::from(test_file("multiline_string", "")).with_file_name("file.rs");
+    fs::write(&file, "a \"one\\\"\ntwo\" b\nr#\"\n\"\" c\\\"# d\n").unwrap();
+    let editor = run(&[file.to_str().unwrap()], Config::default(), vec![Event::Key(Key::Alt('f')), Event::Key(Key::Alt('f'))]);
+
+    let terminal = editor.terminal().frame();
+    let string = terminal.foreground(2, 0);
+    assert!(string.is_some());
+    assert_eq!(terminal.foreground(0, 0), None);
+    //the escaped quote does not close the string, the one on the next row does
+    assert_eq!(terminal.foreground(7, 0), string);
+    assert_eq!(terminal.foreground(3, 1), string);
+    assert_eq!(terminal.foreground(5, 1), None);
+    //a raw string is only closed by "#, and a backslash in it escapes nothing
+    assert_eq!(terminal.foreground(0, 2), string);
+    assert_eq!(terminal.foreground(0, 3), string);
+    assert_eq!(terminal.foreground(1, 3), string);
+    assert_eq!(terminal.foreground(6, 3), string);
+    assert_eq!(terminal.foreground(8, 3), None);
+}
+
+#[test]
+fn line_numbers_in_the_gutter() {
+    let file = test_file("gutter", "a\nb\nc\n");
+    let mut config = Config::default();
+    config.set("line_numbers=relative").unwrap();
+    let editor = run(&[&file], config, vec![Event::Key(Key::Alt('f'))]);
+
+    let terminal = editor.terminal();
+    assert_eq!(&terminal.line(0)[..6], "   1 a");
+    assert_eq!(&terminal.line(1)[..6], "   2 b");
+    assert_eq!(&terminal.line(2)[..6], "   1 c");
+    assert_eq!(terminal.cursor(), Position { x: 5, y: 1 });
+}
+
+#[test]
+fn unsaved_changes_are_kept_when_the_input_ends() {
+    let file = test_file("swap", "text\n");
+    let editor = run(&[&file], Config::default(), typed("changed "));
+
+    let swap = PathBuf::from(&file).with_file_name(".file.txt.swp");
+    assert!(editor.document().is_dirty());
+    assert_eq!(fs::read_to_string(swap).unwrap(), "changed text\n");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "text\n");
+}
+
+#[test]
+fn swap_files_get_the_permissions_of_the_file() {
+    let file = test_file("swap_permissions", "secret\n");
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+    run(&[&file], Config::default(), typed("more "));
+
+    let swap = PathBuf::from(&file).with_file_name(".file.txt.swp");
+    assert_eq!(fs::metadata(swap).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[test]
+fn the_swap_file_can_be_shown_next_to_the_file() {
+    let file = test_file("swap_diff", "one\ntwo\n");
+    let swap = PathBuf::from(&file).with_file_name(".file.txt.swp");
+    fs::write(&swap, "one\nlost\n").unwrap();
+    let later = fs::metadata(&file).unwrap().modified().unwrap() + std::time::Duration::from_secs(1);
+    fs::File::options().write(true).open(&swap).unwrap().set_modified(later).unwrap();
+
+    let editor = run(&[&file], Config::default(), typed("di"));
+
+    let lines = editor.terminal().lines();
+    assert!(lines[1].contains("two") && lines[1].contains("lost"));
+    assert!(lines[1].find("two") < lines[1].find("lost"));
+    assert_eq!(row_text(&editor, 1), "two");
+    assert_eq!(editor.cursor_position(), Position { x: 0, y: 1 });
+    assert_eq!(fs::read_to_string(&swap).unwrap(), "one\nlost\n");
+}
+
+#[test]
+fn ignoring_a_recovery_keeps_the_swap_file() {
+    let file = test_file("swap_ignored", "text\n");
+    let swap = PathBuf::from(&file).with_file_name(".file.txt.swp");
+    fs::write(&swap, "lost text\n").unwrap();
+    let later = fs::metadata(&file).unwrap().modified().unwrap() + std::time::Duration::from_secs(1);
+    fs::File::options().write(true).open(&swap).unwrap().set_modified(later).unwrap();
+
+    let mut events = typed("i");
+    events.extend(typed("new "));
+    run(&[&file], Config::default(), events);
+
+    let alternate = PathBuf::from(&file).with_file_name(".file.txt.swo");
+    assert_eq!(fs::read_to_string(&swap).unwrap(), "lost text\n");
+    assert_eq!(fs::read_to_string(&alternate).unwrap(), "new text\n");
+}
+
+//a mouse event at a cell of the screen (counting from 0, unlike the terminal)
+fn mouse(event : fn(u16, u16) -> MouseEvent, x : u16, y : u16) -> Event {
+    return Event::Mouse(event(x + 1, y + 1));
+}
+
+fn click(x : u16, y : u16) -> Event {
+    return mouse(|x, y| MouseEvent::Press(MouseButton::Left, x, y), x, y);
+}
+
+#[test]
+fn clicking_places_the_cursor() {
+    let file = test_file("click", "first\n\tsecond\nthird\n");
+    let mut config = Config::default();
+    config.set("line_numbers=absolute").unwrap();
+    let editor = run(&[&file], config, vec![click(5 + 6, 1), mouse(MouseEvent::Release, 5 + 6, 1)]);
+    assert_eq!(editor.cursor_position(), Position { x: 3, y: 1 });
+
+    let editor = run(&[&file], Config::default(), vec![click(30, 2)]);
+    assert_eq!(editor.cursor_position(), Position { x: 5, y: 2 });
+    let editor = run(&[&file], Config::default(), vec![click(2, 5)]);
+    assert_eq!(editor.cursor_position(), Position { x: 5, y: 2 });
+}
+
+#[test]
+fn dragging_selects() {
+    let file = test_file("drag", "one two three\nfour five\n");
+    let editor = run(&[&file], Config::default(), vec![
+        click(4, 0),
+        mouse(MouseEvent::Hold, 6, 0),
+        mouse(MouseEvent::Hold, 5, 1),
+        mouse(MouseEvent::Release, 5, 1),
+        Event::Key(Key::Backspace),
+    ]);
+
+    assert_eq!(row_text(&editor, 0), "one five");
+    assert_eq!(editor.cursor_position(), Position { x: 4, y: 0 });
+}
+
+#[test]
+fn the_wheel_scrolls_the_view() {
+    let contents : String = (1..=30).map(|line| format!("line {}\n", line)).collect();
+    let file = test_file("wheel", &contents);
+    let wheel_down = mouse(|x, y| MouseEvent::Press(MouseButton::WheelDown, x, y), 0, 0);
+    let editor = run(&[&file], Config::default(), vec![wheel_down.clone(), wheel_down]);
+
+    assert!(editor.terminal().line(0).starts_with("line 7 "));
+    assert_eq!(editor.cursor_position(), Position { x: 0, y: 6 });
+}
+
+#[test]
+fn clicking_the_buffer_number_switches_buffers() {
+    let first = test_file("status_first", "first\n");
+    let second = test_file("status_second", "second\n");
+    let editor = run(&[&first, &second], Config::default(), vec![click(30, 6)]);
+    assert_eq!(row_text(&editor, 0), "first");
+
+    let editor = run(&[&first, &second], Config::default(), vec![click(2, 6)]);
+    assert_eq!(row_text(&editor, 0), "second");
+    assert!(editor.terminal().line(6).starts_with("[2/2]"));
+}
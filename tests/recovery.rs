@@ -0,0 +1,42 @@
+use std::fs;
+use std::path::PathBuf;
+use text_editor::{typed, Config, Document, Editor, HeadlessTerminal, Position};
+
+//kept in a process of its own, as the swap file left behind here would be offered to every other [No Name] buffer in the same state
+//directory
+#[test]
+fn recovers_a_no_name_buffer_left_behind() {
+    let state : PathBuf = std::env::temp_dir().join("text_editor_recovery");
+    let _ = fs::remove_dir_all(&state);
+    std::env::set_var("XDG_STATE_HOME", &state);
+    let swap_dir = state.join("text_editor");
+    fs::create_dir_all(&swap_dir).unwrap();
+    //no process has pid 0... asking about it would ask about the whole process group instead
+    let orphan = swap_dir.join("no-name-0-1.swp");
+    fs::write(&orphan, "unsaved\n").unwrap();
+
+    let mut editor = Editor::create(HeadlessTerminal::create(100, 8, typed("r")), &[], Config::default(), &[]);
+    editor.run();
+
+    let row = editor.document().row(0).unwrap();
+    assert_eq!(String::from_utf8(row.as_bytes().to_vec()).unwrap(), "unsaved");
+    assert!(editor.document().is_dirty());
+    assert!(orphan.exists() == false);
+    let own = |contents : &str| {
+        return fs::read_dir(&swap_dir).unwrap().flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&format!("no-name-{}-", std::process::id())))
+            .any(|entry| fs::read_to_string(entry.path()).unwrap() == contents);
+    };
+    assert!(own("unsaved\n"));
+
+    //every [No Name] buffer has a swap file of its own
+    let mut first = Document::no_name();
+    let mut second = Document::no_name();
+    first.insert(&Position::default(), 'a');
+    second.insert(&Position::default(), 'b');
+    first.write_swap_file().unwrap();
+    second.write_swap_file().unwrap();
+    assert!(own("a\n") && own("b\n"));
+    first.remove_swap_file();
+    assert!(own("a\n") == false && own("b\n"));
+}